@@ -0,0 +1,320 @@
+use ansi_term::{Color, Style};
+use std::collections::BTreeMap;
+use std::env;
+use std::io::IsTerminal;
+
+use super::report::split_names;
+use super::structs::*;
+
+// The columns of the board, in workflow order. Done only with --done.
+const BOARD_STATUSES: &[TodoStatus] = &[
+    TodoStatus::New,
+    TodoStatus::Backlog,
+    TodoStatus::Next,
+    TodoStatus::Planned,
+    TodoStatus::Doing,
+    TodoStatus::Review,
+    TodoStatus::Done,
+];
+
+// A card shows at most this many lines of its description.
+const CARD_LINES: usize = 3;
+const COLUMN_GAP: &str = "  ";
+const MIN_COLUMN_WIDTH: usize = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BoardLanes {
+    Project,
+    Category,
+}
+
+impl BoardLanes {
+    pub fn from_string(s: &str) -> Result<BoardLanes, String> {
+        match s {
+            "project" | "proj" => Ok(BoardLanes::Project),
+            "category" | "cat" => Ok(BoardLanes::Category),
+            _ => Err(format!("Unknown swimlanes {}, use project or category", s)),
+        }
+    }
+}
+
+fn status_from_name(s: &str) -> Option<TodoStatus> {
+    BOARD_STATUSES
+        .iter()
+        .find(|status| status.to_string().eq_ignore_ascii_case(s))
+        .copied()
+}
+
+// WIP limits like doing:3,review:2.
+pub fn parse_wip_limits(s: &str) -> Result<Vec<(TodoStatus, usize)>, String> {
+    let mut limits = Vec::new();
+    for part in s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        let (name, limit) = match part.split_once(':') {
+            Some(v) => v,
+            None => return Err(format!("Invalid WIP limit {}, use status:limit", part)),
+        };
+        let status = match status_from_name(name.trim()) {
+            Some(v) => v,
+            None => return Err(format!("Unknown status {} in WIP limit", name)),
+        };
+        let limit = match limit.trim().parse::<usize>() {
+            Ok(v) => v,
+            Err(_) => return Err(format!("Invalid WIP limit {}, use status:limit", part)),
+        };
+        limits.push((status, limit));
+    }
+    Ok(limits)
+}
+
+// The width of the terminal, COLUMNS or 80 when the output is not one.
+pub fn output_width() -> usize {
+    if std::io::stdout().is_terminal() {
+        if let Ok((width, _)) = crossterm::terminal::size() {
+            return width as usize;
+        }
+    }
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(80)
+}
+
+// Wraps words to lines of at most width chars. Longer words are cut, more
+// than max_lines lines end in an ellipsis.
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() <= width {
+            line.push(' ');
+            line.extend(word.iter());
+            continue;
+        }
+        if line_len > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        while word.len() > width {
+            lines.push(word.drain(..width).collect());
+        }
+        line = word.into_iter().collect();
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = &mut lines[max_lines - 1];
+        if last.chars().count() >= width {
+            last.pop();
+        }
+        last.push('…');
+    }
+    lines
+}
+
+// One line of the board: cells padded to the column width, cut at the
+// width of the terminal. Colors are added after cutting.
+fn board_line(cells: &[(String, Style)], column_width: usize, width: usize, color: bool) -> String {
+    let mut line = String::new();
+    let mut left = width;
+    for (i, (text, style)) in cells.iter().enumerate() {
+        let gap = if i == 0 { "" } else { COLUMN_GAP };
+        let padded = format!("{}{:<w$}", gap, text, w = column_width);
+        let cut: String = padded.chars().take(left).collect();
+        left -= cut.chars().count();
+        let (gap, text) = cut.split_at(gap.len().min(cut.len()));
+        line.push_str(gap);
+        if color {
+            line.push_str(&style.paint(text).to_string());
+        } else {
+            line.push_str(text);
+        }
+        if left == 0 {
+            break;
+        }
+    }
+    line.trim_end().to_string()
+}
+
+pub struct BoardStyle {
+    pub width: usize,
+    pub color: bool,
+    pub warn_color: Color,
+    pub wip_limits: Vec<(TodoStatus, usize)>,
+}
+
+struct Card {
+    lines: Vec<String>,
+    style: Style,
+}
+
+fn card(todo: &Todo, column_width: usize) -> Card {
+    let color = if todo.done() {
+        Color::Fixed(8)
+    } else {
+        todo.get_color()
+    };
+    // Continued lines are indented to tell the cards apart.
+    let text = format!("{} {}", todo.get_id(), todo.get_description());
+    let lines = wrap(&text, column_width - 2, CARD_LINES)
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| if i == 0 { line } else { format!("  {}", line) })
+        .collect();
+    Card {
+        lines,
+        style: Style::from(color),
+    }
+}
+
+// The cards of every column stacked, one line per row of the board.
+fn column_rows(columns: &[Vec<Card>]) -> Vec<Vec<(String, Style)>> {
+    let mut stacked: Vec<Vec<(String, Style)>> = Vec::new();
+    for cards in columns.iter() {
+        stacked.push(
+            cards
+                .iter()
+                .flat_map(|card| {
+                    card.lines
+                        .iter()
+                        .map(move |line| (line.clone(), card.style))
+                })
+                .collect(),
+        );
+    }
+    let height = stacked.iter().map(|v| v.len()).max().unwrap_or(0);
+    (0..height)
+        .map(|row| {
+            stacked
+                .iter()
+                .map(|lines| {
+                    lines
+                        .get(row)
+                        .cloned()
+                        .unwrap_or_else(|| (String::new(), Style::new()))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Renders the todos as columns by status, optionally in swimlanes. Returns
+// the board and a warning for every column over its WIP limit.
+pub fn render_board(
+    todos: &[Todo],
+    lanes: Option<BoardLanes>,
+    show_done: bool,
+    style: &BoardStyle,
+) -> (String, Vec<String>) {
+    let limit = |status: TodoStatus| {
+        style
+            .wip_limits
+            .iter()
+            .find(|(s, _)| *s == status)
+            .map(|(_, v)| *v)
+    };
+    let count = |status: TodoStatus| todos.iter().filter(|t| t.get_status() == status).count();
+
+    // Empty columns are left out unless they have a WIP limit.
+    let statuses: Vec<TodoStatus> = BOARD_STATUSES
+        .iter()
+        .copied()
+        .filter(|status| show_done || *status != TodoStatus::Done)
+        .filter(|status| count(*status) > 0 || limit(*status).is_some())
+        .collect();
+    if statuses.is_empty() {
+        return (String::from("No Todos\n"), Vec::new());
+    }
+
+    let gaps = COLUMN_GAP.len() * (statuses.len() - 1);
+    let column_width = (style.width.saturating_sub(gaps) / statuses.len()).max(MIN_COLUMN_WIDTH);
+
+    let mut out = String::new();
+    let mut warnings = Vec::new();
+    let mut header = Vec::new();
+    for status in statuses.iter() {
+        let n = count(*status);
+        match limit(*status) {
+            Some(limit) => {
+                let over = n > limit;
+                if over {
+                    warnings.push(format!(
+                        "Warning: {} has {} Todos, the WIP limit is {}",
+                        status, n, limit
+                    ));
+                }
+                let title_style = if over {
+                    style.warn_color.bold()
+                } else {
+                    Style::new().bold()
+                };
+                header.push((format!("{} ({}/{})", status, n, limit), title_style));
+            }
+            None => header.push((format!("{} ({})", status, n), Style::new().bold())),
+        }
+    }
+    out.push_str(&board_line(&header, column_width, style.width, style.color));
+    out.push('\n');
+    let rule: Vec<(String, Style)> = statuses
+        .iter()
+        .map(|_| ("─".repeat(column_width), Style::new()))
+        .collect();
+    out.push_str(&board_line(&rule, column_width, style.width, style.color));
+    out.push('\n');
+
+    // A todo with several projects or categories is in each of their lanes.
+    let mut lane_todos: BTreeMap<String, Vec<&Todo>> = BTreeMap::new();
+    match lanes {
+        Some(lanes) => {
+            for todo in todos.iter() {
+                let names = match lanes {
+                    BoardLanes::Project => split_names(&todo.get_projects()),
+                    BoardLanes::Category => split_names(&todo.get_categories()),
+                };
+                for name in names {
+                    lane_todos.entry(name).or_default().push(todo);
+                }
+            }
+        }
+        None => {
+            lane_todos.insert(String::new(), todos.iter().collect());
+        }
+    }
+    // Todos without a project or category come last.
+    let mut lane_names: Vec<&String> = lane_todos.keys().collect();
+    lane_names.sort_by_key(|name| *name == "(none)");
+
+    for (i, name) in lane_names.into_iter().enumerate() {
+        if lanes.is_some() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let title = if style.color {
+                Style::new().bold().underline().paint(name).to_string()
+            } else {
+                name.to_string()
+            };
+            out.push_str(&title);
+            out.push('\n');
+        }
+        let columns: Vec<Vec<Card>> = statuses
+            .iter()
+            .map(|status| {
+                lane_todos[name]
+                    .iter()
+                    .filter(|todo| todo.get_status() == *status)
+                    .map(|todo| card(todo, column_width))
+                    .collect()
+            })
+            .collect();
+        for row in column_rows(&columns) {
+            out.push_str(&board_line(&row, column_width, style.width, style.color));
+            out.push('\n');
+        }
+    }
+    (out, warnings)
+}
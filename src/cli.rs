@@ -0,0 +1,1101 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use super::board::*;
+use super::helper::*;
+use super::output::*;
+use super::report::*;
+use super::sort::*;
+
+// Exit codes: the command failed, or the command line was wrong.
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+
+// What t set can change.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Attribute {
+    Priority,
+    Description,
+    Projects,
+    Categories,
+    Estimated,
+    Actual,
+    Status,
+    Color,
+    Due,
+    Scheduled,
+    Recurrence,
+    Parent,
+}
+
+impl Attribute {
+    fn from_name(s: &str) -> Option<Attribute> {
+        match s {
+            "prio" => Some(Attribute::Priority),
+            "desc" => Some(Attribute::Description),
+            "proj" => Some(Attribute::Projects),
+            "cat" => Some(Attribute::Categories),
+            "est" => Some(Attribute::Estimated),
+            "act" => Some(Attribute::Actual),
+            "stat" => Some(Attribute::Status),
+            "color" => Some(Attribute::Color),
+            "due" => Some(Attribute::Due),
+            "sched" => Some(Attribute::Scheduled),
+            "recur" => Some(Attribute::Recurrence),
+            "parent" => Some(Attribute::Parent),
+            _ => None,
+        }
+    }
+}
+
+// The todos a command works on: ids like 3,5,8-12 or a query (--where).
+// The ranges are only expanded against the todos that exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    Ids(Vec<RangeInclusive<usize>>),
+    Query(String),
+}
+
+impl Selection {
+    // A single id, where the output stays as it was before id lists.
+    pub fn is_single(&self) -> bool {
+        matches!(self, Selection::Ids(ranges) if ranges.len() == 1 && ranges[0].start() == ranges[0].end())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RmTarget {
+    All,
+    Todos(Selection),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DepAction {
+    Add,
+    Rm,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListAction {
+    Ls,
+    New(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigAction {
+    List,
+    Get(String),
+    Set(String, String),
+}
+
+#[derive(Debug)]
+pub enum Command {
+    New {
+        parent: Option<usize>,
+        raw: bool,
+        words: Vec<String>,
+        format: OutputFormat,
+    },
+    Set {
+        attribute: Attribute,
+        selection: Selection,
+        value: String,
+        dry_run: bool,
+        format: OutputFormat,
+    },
+    Edit {
+        selection: Selection,
+    },
+    // Without text the note is read from stdin.
+    Note {
+        id: usize,
+        text: Option<String>,
+    },
+    Show {
+        id: usize,
+    },
+    Do {
+        selection: Selection,
+        dry_run: bool,
+        format: OutputFormat,
+    },
+    Rm {
+        target: RmTarget,
+        dry_run: bool,
+        format: OutputFormat,
+    },
+    Ls {
+        show_done: bool,
+        ready: bool,
+        all_lists: bool,
+        sort: Option<SortOrder>,
+        query: String,
+        format: OutputFormat,
+    },
+    Dep {
+        action: DepAction,
+        id: usize,
+        on: usize,
+    },
+    Clean,
+    Start {
+        id: usize,
+    },
+    Stop {
+        id: Option<usize>,
+    },
+    Status,
+    Report {
+        from: Option<String>,
+        to: Option<String>,
+        grouping: ReportGrouping,
+        csv: bool,
+        filter: String,
+    },
+    Export,
+    Import {
+        path: String,
+    },
+    Undo,
+    Redo,
+    Log {
+        compact: bool,
+    },
+    List(ListAction),
+    Move {
+        id: usize,
+        list: String,
+        format: OutputFormat,
+    },
+    Config(ConfigAction),
+    Board {
+        lanes: Option<BoardLanes>,
+        show_done: bool,
+        query: String,
+    },
+    Tui,
+    Help {
+        topic: Option<String>,
+    },
+}
+
+impl Command {
+    // Commands that change todos or lists hold the lock while they run. The
+    // TUI takes it for each change, t edit once the editor is closed.
+    pub fn writes(&self) -> bool {
+        !matches!(
+            self,
+            Command::Ls { .. }
+                | Command::Status
+                | Command::Report { .. }
+                | Command::Export
+                | Command::List(ListAction::Ls)
+                | Command::Config(_)
+                | Command::Board { .. }
+                | Command::Edit { .. }
+                | Command::Show { .. }
+                | Command::Tui
+                | Command::Help { .. }
+        )
+    }
+}
+
+// The parsed command line. `line` is the command as typed, without the
+// options in front, for the journal.
+#[derive(Debug)]
+pub struct Cli {
+    pub list: Option<String>,
+    pub file: Option<PathBuf>,
+    pub command: Command,
+    pub line: String,
+}
+
+// A command line that does not fit the command. Shown with its usage.
+#[derive(Debug)]
+pub struct UsageError {
+    msg: String,
+    command: Option<&'static str>,
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Error: {}", self.msg)?;
+        match self.command.and_then(find_help) {
+            Some(help) => {
+                writeln!(f, "usage:")?;
+                for line in help.usage {
+                    writeln!(f, "{}", line)?;
+                }
+                write!(f, "see t help {}", help.name)
+            }
+            None => write!(f, "see t help"),
+        }
+    }
+}
+
+impl UsageError {
+    fn new(command: &'static str, msg: &str) -> UsageError {
+        UsageError {
+            msg: msg.to_string(),
+            command: Some(command),
+        }
+    }
+}
+
+fn parse_id(command: &'static str, s: &str) -> Result<usize, UsageError> {
+    match s.parse::<usize>() {
+        Ok(v) => Ok(v),
+        Err(_) => Err(UsageError::new(command, &format!("Invalid ID {}", s))),
+    }
+}
+
+// A comma separated list of ids and ranges like 3,5,8-12.
+fn parse_ids(command: &'static str, s: &str) -> Result<Vec<RangeInclusive<usize>>, UsageError> {
+    let mut ranges = Vec::new();
+    for part in s.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_id(command, first)?, parse_id(command, last)?),
+            None => {
+                let id = parse_id(command, part)?;
+                (id, id)
+            }
+        };
+        if first > last {
+            return Err(UsageError::new(
+                command,
+                &format!("Invalid range {}, the first ID is larger", part),
+            ));
+        }
+        ranges.push(first..=last);
+    }
+    Ok(ranges)
+}
+
+// --where <query>, otherwise the ids in the next argument.
+fn take_selection(
+    command: &'static str,
+    args: &mut Vec<String>,
+    position: usize,
+) -> Result<Selection, UsageError> {
+    if let Some(v) = take_option_or_error(command, args, "--where")? {
+        return Ok(Selection::Query(v));
+    }
+    if args.len() <= position {
+        return Err(UsageError::new(command, "Missing arguments"));
+    }
+    let ids = args.remove(position);
+    Ok(Selection::Ids(parse_ids(command, &ids)?))
+}
+
+fn take_option_or_error(
+    command: &'static str,
+    args: &mut Vec<String>,
+    name: &str,
+) -> Result<Option<String>, UsageError> {
+    take_option(args, name).map_err(|err| UsageError::new(command, &err))
+}
+
+fn take_format(command: &'static str, args: &mut Vec<String>) -> Result<OutputFormat, UsageError> {
+    match take_option_or_error(command, args, "--format")? {
+        Some(v) => OutputFormat::from_string(&v).map_err(|err| UsageError::new(command, &err)),
+        None => Ok(OutputFormat::Table),
+    }
+}
+
+// The arguments left after the options, between min and max of them.
+fn expect_args(
+    command: &'static str,
+    args: &[String],
+    min: usize,
+    max: usize,
+) -> Result<(), UsageError> {
+    if let Some(option) = args.iter().find(|v| v.starts_with("--")) {
+        return Err(UsageError::new(
+            command,
+            &format!("Unknown option {}", option),
+        ));
+    }
+    if args.len() < min {
+        return Err(UsageError::new(command, "Missing arguments"));
+    }
+    if args.len() > max {
+        return Err(UsageError::new(
+            command,
+            &format!("Unexpected argument {}", args[max]),
+        ));
+    }
+    Ok(())
+}
+
+fn parse_command(name: &str, mut args: Vec<String>) -> Result<Command, UsageError> {
+    let command = match name {
+        "new" => {
+            let words = split_free_text(&mut args, 0, &["--parent", "--format"]);
+            let format = take_format("new", &mut args)?;
+            let raw = take_flag(&mut args, "--raw");
+            let parent = match take_option_or_error("new", &mut args, "--parent")? {
+                Some(v) => Some(parse_id("new", &v)?),
+                None => None,
+            };
+            expect_args("new", &args, 0, 0)?;
+            if words.is_empty() {
+                return Err(UsageError::new("new", "Missing description"));
+            }
+            Command::New {
+                parent,
+                raw,
+                words,
+                format,
+            }
+        }
+        "set" => {
+            let value = split_free_text(&mut args, 2, &["--format", "--where"]);
+            let format = take_format("set", &mut args)?;
+            let dry_run = take_flag(&mut args, "--dry-run");
+            if args.is_empty() {
+                return Err(UsageError::new("set", "Missing arguments"));
+            }
+            let attribute = match Attribute::from_name(&args[0]) {
+                Some(v) => v,
+                None => {
+                    return Err(UsageError::new(
+                        "set",
+                        &format!("No such attribute: {}", args[0]),
+                    ))
+                }
+            };
+            let selection = take_selection("set", &mut args, 1)?;
+            expect_args("set", &args, 1, 1)?;
+            if value.is_empty() {
+                return Err(UsageError::new("set", "Missing value"));
+            }
+            Command::Set {
+                attribute,
+                selection,
+                value: value.join(" "),
+                dry_run,
+                format,
+            }
+        }
+        "edit" => {
+            let selection = take_selection("edit", &mut args, 0)?;
+            expect_args("edit", &args, 0, 0)?;
+            Command::Edit { selection }
+        }
+        "note" => {
+            let text = split_free_text(&mut args, 1, &[]);
+            expect_args("note", &args, 1, 1)?;
+            Command::Note {
+                id: parse_id("note", &args[0])?,
+                text: if text.is_empty() {
+                    None
+                } else {
+                    Some(text.join(" "))
+                },
+            }
+        }
+        "show" => {
+            expect_args("show", &args, 1, 1)?;
+            Command::Show {
+                id: parse_id("show", &args[0])?,
+            }
+        }
+        "do" => {
+            let format = take_format("do", &mut args)?;
+            let dry_run = take_flag(&mut args, "--dry-run");
+            let selection = take_selection("do", &mut args, 0)?;
+            expect_args("do", &args, 0, 0)?;
+            Command::Do {
+                selection,
+                dry_run,
+                format,
+            }
+        }
+        "rm" => {
+            let format = take_format("rm", &mut args)?;
+            let dry_run = take_flag(&mut args, "--dry-run");
+            let target = if args.first().map(|v| &v[..]) == Some("all") {
+                args.remove(0);
+                RmTarget::All
+            } else {
+                RmTarget::Todos(take_selection("rm", &mut args, 0)?)
+            };
+            expect_args("rm", &args, 0, 0)?;
+            Command::Rm {
+                target,
+                dry_run,
+                format,
+            }
+        }
+        "ls" | "lsa" => {
+            let command = if name == "ls" { "ls" } else { "lsa" };
+            let format = take_format(command, &mut args)?;
+            let ready = take_flag(&mut args, "--ready");
+            let all_lists = take_flag(&mut args, "--all-lists");
+            let sort = match take_option_or_error(command, &mut args, "--sort")? {
+                Some(v) => {
+                    Some(SortOrder::parse(&v).map_err(|err| UsageError::new(command, &err))?)
+                }
+                None => None,
+            };
+            expect_args(command, &args, 0, usize::MAX)?;
+            Command::Ls {
+                show_done: name == "lsa",
+                ready,
+                all_lists,
+                sort,
+                query: args.join(" "),
+                format,
+            }
+        }
+        "dep" => {
+            expect_args("dep", &args, 3, 3)?;
+            let action = match &args[0][..] {
+                "add" => DepAction::Add,
+                "rm" => DepAction::Rm,
+                v => {
+                    return Err(UsageError::new(
+                        "dep",
+                        &format!("No such dependency command: {}", v),
+                    ))
+                }
+            };
+            Command::Dep {
+                action,
+                id: parse_id("dep", &args[1])?,
+                on: parse_id("dep", &args[2])?,
+            }
+        }
+        "clean" => {
+            expect_args("clean", &args, 0, 0)?;
+            Command::Clean
+        }
+        "start" => {
+            expect_args("start", &args, 1, 1)?;
+            Command::Start {
+                id: parse_id("start", &args[0])?,
+            }
+        }
+        "stop" => {
+            expect_args("stop", &args, 0, 1)?;
+            Command::Stop {
+                id: match args.first() {
+                    Some(v) => Some(parse_id("stop", v)?),
+                    None => None,
+                },
+            }
+        }
+        "status" => {
+            expect_args("status", &args, 0, 0)?;
+            Command::Status
+        }
+        "report" => {
+            let from = take_option_or_error("report", &mut args, "--from")?;
+            let to = take_option_or_error("report", &mut args, "--to")?;
+            let by = take_option_or_error("report", &mut args, "--by")?;
+            let format = take_option_or_error("report", &mut args, "--format")?;
+            let filter = take_option_or_error("report", &mut args, "--where")?;
+            expect_args("report", &args, 1, 1)?;
+            if args[0] != "time" {
+                return Err(UsageError::new(
+                    "report",
+                    &format!("Unknown report {}, use time", args[0]),
+                ));
+            }
+            let grouping = ReportGrouping::from_string(by.as_deref().unwrap_or("project"))
+                .map_err(|err| UsageError::new("report", &err))?;
+            let csv = match format.as_deref() {
+                None | Some("table") => false,
+                Some("csv") => true,
+                Some(v) => {
+                    return Err(UsageError::new(
+                        "report",
+                        &format!("Unknown format {}, use table or csv", v),
+                    ))
+                }
+            };
+            Command::Report {
+                from,
+                to,
+                grouping,
+                csv,
+                filter: filter.unwrap_or_default(),
+            }
+        }
+        "export" => {
+            expect_args("export", &args, 1, 1)?;
+            if args[0] != "todotxt" {
+                return Err(UsageError::new(
+                    "export",
+                    &format!("Unknown format {}, use todotxt", args[0]),
+                ));
+            }
+            Command::Export
+        }
+        "import" => {
+            // - is stdin, not an option.
+            if args.len() != 2 {
+                return Err(UsageError::new("import", "Expected a format and a file"));
+            }
+            if args[0] != "todotxt" {
+                return Err(UsageError::new(
+                    "import",
+                    &format!("Unknown format {}, use todotxt", args[0]),
+                ));
+            }
+            Command::Import {
+                path: args[1].clone(),
+            }
+        }
+        "undo" => {
+            expect_args("undo", &args, 0, 0)?;
+            Command::Undo
+        }
+        "redo" => {
+            expect_args("redo", &args, 0, 0)?;
+            Command::Redo
+        }
+        "log" => {
+            expect_args("log", &args, 0, 1)?;
+            match args.first().map(|v| &v[..]) {
+                None => Command::Log { compact: false },
+                Some("compact") => Command::Log { compact: true },
+                Some(v) => {
+                    return Err(UsageError::new(
+                        "log",
+                        &format!("Unexpected argument {}", v),
+                    ))
+                }
+            }
+        }
+        "list" => {
+            expect_args("list", &args, 0, 2)?;
+            match (args.first().map(|v| &v[..]), args.len()) {
+                (None, _) | (Some("ls"), 1) => Command::List(ListAction::Ls),
+                (Some("new"), 2) => Command::List(ListAction::New(args[1].clone())),
+                (Some("new"), _) => return Err(UsageError::new("list", "Missing list name")),
+                (Some(v), _) => {
+                    return Err(UsageError::new(
+                        "list",
+                        &format!("No such list command: {}", v),
+                    ))
+                }
+            }
+        }
+        "move" => {
+            let format = take_format("move", &mut args)?;
+            expect_args("move", &args, 2, 2)?;
+            Command::Move {
+                id: parse_id("move", &args[0])?,
+                list: args[1].clone(),
+                format,
+            }
+        }
+        "config" => match args.first().map(|v| &v[..]) {
+            None | Some("list") if args.len() <= 1 => Command::Config(ConfigAction::List),
+            Some("get") if args.len() == 2 => Command::Config(ConfigAction::Get(args[1].clone())),
+            Some("set") if args.len() >= 3 => {
+                Command::Config(ConfigAction::Set(args[1].clone(), args[2..].join(" ")))
+            }
+            Some("get") | Some("set") => {
+                return Err(UsageError::new("config", "Missing arguments"))
+            }
+            Some(v) => {
+                return Err(UsageError::new(
+                    "config",
+                    &format!("No such config command: {}", v),
+                ))
+            }
+            None => unreachable!(),
+        },
+        "board" => {
+            let show_done = take_flag(&mut args, "--done");
+            let lanes = match take_option_or_error("board", &mut args, "--by")? {
+                Some(v) => Some(
+                    BoardLanes::from_string(&v).map_err(|err| UsageError::new("board", &err))?,
+                ),
+                None => None,
+            };
+            expect_args("board", &args, 0, usize::MAX)?;
+            Command::Board {
+                lanes,
+                show_done,
+                query: args.join(" "),
+            }
+        }
+        "tui" => {
+            expect_args("tui", &args, 0, 0)?;
+            Command::Tui
+        }
+        "help" | "--help" | "-h" => {
+            if args.len() > 1 {
+                return Err(UsageError::new("help", "Expected one command or topic"));
+            }
+            Command::Help {
+                topic: args.first().cloned(),
+            }
+        }
+        _ => {
+            return Err(UsageError {
+                msg: format!("Unknown command {}", name),
+                command: None,
+            })
+        }
+    };
+    Ok(command)
+}
+
+// Parses everything after the program name. -l <list> and --file <path>
+// come before the command.
+pub fn parse_args(mut args: Vec<String>) -> Result<Cli, UsageError> {
+    let mut list = None;
+    let mut file = None;
+    while !args.is_empty() && matches!(&args[0][..], "-l" | "--list" | "--file") {
+        if args.len() < 2 {
+            return Err(UsageError {
+                msg: format!("{} needs a value", args[0]),
+                command: None,
+            });
+        }
+        let option = args.remove(0);
+        let value = args.remove(0);
+        if option == "--file" {
+            file = Some(PathBuf::from(value));
+        } else {
+            list = Some(value);
+        }
+    }
+
+    let line = args.join(" ");
+    let command = if args.is_empty() {
+        Command::Help { topic: None }
+    } else {
+        let name = args.remove(0);
+        parse_command(&name, args)?
+    };
+    Ok(Cli {
+        list,
+        file,
+        command,
+        line,
+    })
+}
+
+struct CommandHelp {
+    name: &'static str,
+    usage: &'static [&'static str],
+    help: &'static str,
+}
+
+const COMMANDS: &[CommandHelp] = &[
+    CommandHelp {
+        name: "new",
+        usage: &["t new [--parent <id>] [--raw] [Prio] <description> # see Quick add"],
+        help: "Adds a todo. A number in front is the priority. Unless --raw is given,\n\
+               +project @category !prio est: act: due: sched: and #color in the\n\
+               description fill in the fields, see t help quick-add. Options go in\n\
+               front of the description, -- ends them early.",
+    },
+    CommandHelp {
+        name: "set",
+        usage: &["t set [--dry-run] (prio|desc|proj|cat|est|act|stat|color|due|sched|recur|parent) <ids>|--where <query> <value>"],
+        help: "Changes one attribute of todos. The value can be several words and\n\
+               is kept as typed, options go in front of it.\n\
+               Dates, durations and recurrence rules are written as in t help dates,\n\
+               t help durations and t help recurrence. See t help ids for <ids>.",
+    },
+    CommandHelp {
+        name: "edit",
+        usage: &["t edit <ids>|--where <query> # all fields in $VISUAL or $EDITOR"],
+        help: "Opens the todos in your editor as key: value lines and saves the\n\
+               changes when it quits. A file with errors can be opened again with\n\
+               the errors fixed. The editor is $VISUAL, $EDITOR or vi.",
+    },
+    CommandHelp {
+        name: "note",
+        usage: &["t note <id> [text] # without text the note is read from stdin"],
+        help: "Adds a note below the notes of a todo. Notes can have several lines,\n\
+               show up in t show and are searched by queries. t edit changes them.",
+    },
+    CommandHelp {
+        name: "show",
+        usage: &["t show <id> # all fields, subtasks, dependencies and notes"],
+        help: "Shows everything about one todo.",
+    },
+    CommandHelp {
+        name: "do",
+        usage: &["t do  <ids>|--where <query> [--dry-run] # including subtasks, recurring todos come back"],
+        help: "Marks todos and their open subtasks as done and stops their timers. A\n\
+               recurring todo comes back as a new todo with the next dates.",
+    },
+    CommandHelp {
+        name: "rm",
+        usage: &["t rm  <ids>|--where <query>|all [--dry-run] # including subtasks"],
+        help: "Removes todos with their subtasks, or every todo of the list.",
+    },
+    CommandHelp {
+        name: "ls",
+        usage: &["t ls  [--ready] [--all-lists] [--sort <keys>] [query] # --ready hides blocked todos"],
+        help: "Lists the open todos that match the query, subtasks below their parent.\n\
+               --all-lists shows the todos of every list, see t help queries and\n\
+               t help sort.",
+    },
+    CommandHelp {
+        name: "lsa",
+        usage: &["t lsa [--ready] [--all-lists] [--sort <keys>] [query]"],
+        help: "Like t ls, including the todos that are done.",
+    },
+    CommandHelp {
+        name: "dep",
+        usage: &["t dep (add|rm) <id> <on-id> # <id> is blocked until <on-id> is done"],
+        help: "Adds or removes a dependency. A todo is blocked as long as one of the\n\
+               todos it depends on is not done.",
+    },
+    CommandHelp {
+        name: "clean",
+        usage: &["t clean # resets the ids"],
+        help: "Numbers the todos from 0 again, in the order of t ls.",
+    },
+    CommandHelp {
+        name: "start",
+        usage: &["t start <id> # starts the timer and sets the status to Doing"],
+        help: "Starts tracking time on a todo. The tracked time is added to its\n\
+               actual time when the timer stops.",
+    },
+    CommandHelp {
+        name: "stop",
+        usage: &["t stop [id] # stops the timer, without id all timers"],
+        help: "Stops the timer of a todo, or every running timer.",
+    },
+    CommandHelp {
+        name: "status",
+        usage: &["t status # shows running timers"],
+        help: "Shows the running timers with their start and the time so far.",
+    },
+    CommandHelp {
+        name: "report",
+        usage: &["t report time [--from <date>] [--to <date>] [--by project|category|day] [--format table|csv] [--where <query>]"],
+        help: "Sums up estimated and actual time per project, category or day. With\n\
+               --from or --to, or by day, only the tracked sessions count. --to\n\
+               includes its day.",
+    },
+    CommandHelp {
+        name: "export",
+        usage: &["t export todotxt # prints all todos in the todo.txt format"],
+        help: "Prints every todo as a todo.txt line, see README for the mapping.",
+    },
+    CommandHelp {
+        name: "import",
+        usage: &["t import todotxt <file> # - reads from stdin"],
+        help: "Adds the todos of a todo.txt file. Their IDs are kept if none of them\n\
+               is taken, otherwise they get new ones.",
+    },
+    CommandHelp {
+        name: "undo",
+        usage: &["t undo"],
+        help: "Undoes the latest change of the list.",
+    },
+    CommandHelp {
+        name: "redo",
+        usage: &["t redo"],
+        help: "Redoes the latest undone change of the list.",
+    },
+    CommandHelp {
+        name: "log",
+        usage: &["t log [compact]"],
+        help: "Shows the recorded changes. compact trims the journal to the last 100.",
+    },
+    CommandHelp {
+        name: "list",
+        usage: &[
+            "t list [ls] # shows all lists, * marks the current one",
+            "t list new <name>",
+        ],
+        help: "Shows or creates lists. Every list has its own todos, IDs and journal,\n\
+               t -l <list> <command> works on one of them.",
+    },
+    CommandHelp {
+        name: "move",
+        usage: &["t move <id> <list> # including its subtasks, they get new ids"],
+        help: "Moves a todo and its subtasks to another list. t undo in the list\n\
+               they came from takes them out of the other list again.",
+    },
+    CommandHelp {
+        name: "config",
+        usage: &[
+            "t config [list] # shows all keys with their values",
+            "t config get <key>",
+            "t config set <key> <value>",
+        ],
+        help: "Shows or changes the config file, see README for the keys.",
+    },
+    CommandHelp {
+        name: "board",
+        usage: &["t board [--by project|category] [--done] [query] # columns by status"],
+        help: "Shows the todos that match the query as a board with a column per\n\
+               status. --by adds a swimlane per project or category, --done the Done\n\
+               column. Columns over their WIP limit (config key wip, e.g.\n\
+               doing:3,review:2) are marked and warned about.",
+    },
+    CommandHelp {
+        name: "tui",
+        usage: &["t tui # full screen list, q quits"],
+        help: "Shows the todos of the list in the terminal. Changes are written right\n\
+               away and can be undone with t undo.\n\
+               j/k or arrows move, PageUp/PageDown and g/G jump\n\
+               s/S cycles the status from New to Review, d marks done like t do\n\
+               +/- changes the priority\n\
+               e, p and c edit description, projects and categories, Enter saves\n\
+               / filters with a query while typing, Esc clears it\n\
+               a shows done todos, r reloads, q quits, Esc clears the filter or quits",
+    },
+    CommandHelp {
+        name: "help",
+        usage: &["t help [command|topic]"],
+        help: "Shows the help of a command, or one of the topics ids, dates,\n\
+               quick-add, formats, queries, sort, recurrence, durations, status and\n\
+               colors.",
+    },
+];
+
+// Options in front of every command.
+const GLOBAL_USAGE: &[&str] = &[
+    "t -l <list> <command> # works on another list, default: list in the config",
+    "t --file <path> <command> # uses another todo file for the default list",
+];
+
+const TOPICS: &[(&str, &str)] = &[
+    (
+        "ids",
+        "IDs (set, do and rm):
+3,5,8-12 # several todos in one go
+--where 'proj:web status:new' # every todo that matches, see Queries
+--dry-run # shows what would change without writing it",
+    ),
+    (
+        "dates",
+        "Dates:
+YYYY-MM-DD [HH:MM]
+today, tomorrow, yesterday
+mon, fri, next mon # today counts, next skips it
++3d, 2w, -1m, 1y, 4h
+eow, eom, eoy
+HH:MM # today, or after any of the above: fri 09:00
+none # removes the date",
+    ),
+    (
+        "quick-add",
+        "Quick add (t new), unless --raw is given:
++project @category !prio est:2h act:30m due:fri sched:mon #red
+e.g. t new Fix login +web @bug !3 est:2h due:fri #red",
+    ),
+    (
+        "formats",
+        "Formats (--format for new, set, do, rm, move, ls and lsa):
+table, json, ndjson, csv, tsv",
+    ),
+    (
+        "queries",
+        "Queries:
+status:doing prio>3 proj:web and not cat:bug
+est>2h, act<=30m, due<fri, due:none, sched>=today, parent:4, id!=2
+desc~/^fix (login|signup)/ # regex, case insensitive
+(proj:web or proj:api) not status:review
+login # words without a field search description, projects, categories and notes
+wow! 10:30 # so does anything that is not a field and an operator
+fields: id status prio desc proj cat est act due sched color parent notes
+operators: : (contains for text) = != < <= > >= ~",
+    ),
+    (
+        "sort",
+        "Sort keys:
+prio,-due,proj,id # - reverses a key, todos without the value come last
+fields: id status prio desc proj cat est act due sched color parent default",
+    ),
+    (
+        "recurrence",
+        "Recurrence:
+daily, weekly, monthly, yearly
+every 3 days, every 2 weeks, every mon,thu # keeps the schedule
+after 10 days # counts from the day it was done
+none # stops repeating",
+    ),
+    (
+        "durations",
+        "Durations:
+1h30m, 90m, 1.5h, 2d, 1w # a day is work_day long (8h), a week five days
+3600 # plain seconds",
+    ),
+    (
+        "status",
+        "Possible status:
+Open
+Backlog
+Next
+Planned
+Doing
+Review
+Done",
+    ),
+    (
+        "colors",
+        "Colors:
+Black
+Red
+Green
+Yellow
+Blue
+Purple
+Cyan
+White",
+    ),
+];
+
+const EXIT_CODES: &str = "Exit codes:
+0 # success
+1 # the command failed
+2 # the command line is wrong";
+
+fn find_help(name: &str) -> Option<&'static CommandHelp> {
+    COMMANDS.iter().find(|help| help.name == name)
+}
+
+// The help of a command or topic, everything without one. None if there is
+// no such command or topic.
+pub fn help_text(topic: Option<&str>) -> Option<String> {
+    let topic = match topic {
+        Some(v) => v,
+        None => {
+            let mut lines = vec![String::from("usage:")];
+            for help in COMMANDS.iter() {
+                lines.extend(help.usage.iter().map(|v| v.to_string()));
+            }
+            lines.extend(GLOBAL_USAGE.iter().map(|v| v.to_string()));
+            for (_, text) in TOPICS.iter() {
+                lines.push(format!("\n{}", text));
+            }
+            lines.push(format!("\n{}", EXIT_CODES));
+            return Some(lines.join("\n"));
+        }
+    };
+
+    if let Some(help) = find_help(topic) {
+        let mut lines = vec![String::from("usage:")];
+        lines.extend(help.usage.iter().map(|v| v.to_string()));
+        lines.push(format!("\n{}", help.help));
+        // status is a command and a topic.
+        if let Some((_, text)) = TOPICS.iter().find(|(name, _)| *name == topic) {
+            lines.push(format!("\n{}", text));
+        }
+        return Some(lines.join("\n"));
+    }
+    TOPICS
+        .iter()
+        .find(|(name, _)| *name == topic)
+        .map(|(_, text)| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Command {
+        let args = line.split(' ').map(String::from).collect();
+        parse_args(args).unwrap().command
+    }
+
+    #[test]
+    fn options_inside_a_description_are_kept() {
+        match parse("new Document the --raw flag") {
+            Command::New { raw, words, .. } => {
+                assert!(!raw);
+                assert_eq!(words.join(" "), "Document the --raw flag");
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+        match parse("new --raw --parent 3 Use --parent=4 here") {
+            Command::New {
+                raw, parent, words, ..
+            } => {
+                assert!(raw);
+                assert_eq!(parent, Some(3));
+                assert_eq!(words.join(" "), "Use --parent=4 here");
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+        match parse("new -- --raw is a flag") {
+            Command::New { raw, words, .. } => {
+                assert!(!raw);
+                assert_eq!(words.join(" "), "--raw is a flag");
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+    }
+
+    #[test]
+    fn options_inside_a_value_are_kept() {
+        match parse("set --dry-run desc 3 Run it with --dry-run --format json") {
+            Command::Set {
+                selection,
+                value,
+                dry_run,
+                format,
+                ..
+            } => {
+                assert!(dry_run);
+                assert_eq!(format, OutputFormat::Table);
+                assert_eq!(selection, Selection::Ids(vec![3..=3]));
+                assert_eq!(value, "Run it with --dry-run --format json");
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+        match parse("set desc --where proj:web -- --dry-run") {
+            Command::Set {
+                selection,
+                value,
+                dry_run,
+                ..
+            } => {
+                assert!(!dry_run);
+                assert_eq!(selection, Selection::Query(String::from("proj:web")));
+                assert_eq!(value, "--dry-run");
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+        match parse("note 2 see --raw") {
+            Command::Note { id, text } => {
+                assert_eq!(id, 2);
+                assert_eq!(text.as_deref(), Some("see --raw"));
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+    }
+
+    #[test]
+    fn unknown_options_in_front_are_errors() {
+        let args = ["new", "--rwa", "Fix"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        let err = parse_args(args).unwrap_err();
+        assert_eq!(err.msg, "Unknown option --rwa");
+    }
+
+    #[test]
+    fn ranges_stay_ranges() {
+        match parse("do 3,5,8-12,0-300000") {
+            Command::Do { selection, .. } => {
+                assert_eq!(
+                    selection,
+                    Selection::Ids(vec![3..=3, 5..=5, 8..=12, 0..=300000])
+                );
+                assert!(!selection.is_single());
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+        let args = ["do", "5-3"].iter().map(|v| v.to_string()).collect();
+        let err = parse_args(args).unwrap_err();
+        assert_eq!(err.msg, "Invalid range 5-3, the first ID is larger");
+    }
+}
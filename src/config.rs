@@ -0,0 +1,301 @@
+use chrono::format::{Item, StrftimeItems};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::board::*;
+use super::sort::*;
+use super::structs::parse::*;
+use super::structs::table::*;
+use super::structs::*;
+use super::todoio::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Backend {
+    Tsv,
+    Sqlite,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorMode {
+    // Colors when printing to a terminal and NO_COLOR is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+// Every config key with its default, in the order of t config list.
+const KEYS: &[(&str, &str)] = &[
+    ("dir", "~/.todo"),
+    ("file", ""),
+    ("backend", "tsv"),
+    ("list", DEFAULT_LIST),
+    ("sort", "default"),
+    ("work_day", "8h"),
+    ("color", "auto"),
+    ("overdue_color", "red"),
+    ("today_color", "yellow"),
+    (
+        "columns",
+        "id,status,prio,desc,proj,cat,due,sched,est,act,diff",
+    ),
+    ("date_format", "%Y-%m-%d"),
+    ("time_format", "%H:%M"),
+    ("wip", ""),
+];
+
+#[derive(Debug)]
+pub struct Config {
+    // Where the todos are kept, TODO_DIR overrides it.
+    pub dir: Option<String>,
+    // Replaces the todo file of the default list, TODO_FILE and --file
+    // override it.
+    pub file: Option<String>,
+    pub backend: Backend,
+    // Length of a day (d) in durations like est and act.
+    pub work_day: Duration,
+    // Order of ls and lsa when no --sort is given.
+    pub sort: SortOrder,
+    // The list commands work on when no -l is given.
+    pub list: String,
+    pub color: ColorMode,
+    pub table: TableStyle,
+    // WIP limits of the board columns.
+    pub wip_limits: Vec<(TodoStatus, usize)>,
+    // The values as written in the config file.
+    values: HashMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            dir: None,
+            file: None,
+            backend: Backend::Tsv,
+            work_day: Duration::from_secs(8 * 60 * 60),
+            sort: SortOrder::default(),
+            list: DEFAULT_LIST.to_string(),
+            color: ColorMode::Auto,
+            table: TableStyle::default(),
+            wip_limits: Vec::new(),
+            values: HashMap::new(),
+        }
+    }
+}
+
+fn check_format(value: &str) -> Result<(), String> {
+    if value.is_empty() || StrftimeItems::new(value).any(|item| item == Item::Error) {
+        return Err(format!("Invalid format {}", value));
+    }
+    Ok(())
+}
+
+fn parse_color_or_error(value: &str) -> Result<ansi_term::Color, String> {
+    match parse_color(value) {
+        Some(v) => Ok(v),
+        None => Err(format!(
+            "Unknown color {}, use black, red, green, yellow, blue, purple, cyan or white",
+            value
+        )),
+    }
+}
+
+impl Config {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "dir" => self.dir = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "file" => self.file = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "backend" => {
+                self.backend = match value {
+                    "tsv" => Backend::Tsv,
+                    "sqlite" => Backend::Sqlite,
+                    _ => return Err(format!("Unknown backend {}, use tsv or sqlite", value)),
+                }
+            }
+            "work_day" => {
+                self.work_day = match parse_duration_input(value, Duration::from_secs(0)) {
+                    Ok(Some(v)) if v.as_secs() > 0 => v,
+                    Ok(_) => return Err(String::from("work_day must be longer than 0")),
+                    Err(err) => return Err(err.to_string()),
+                }
+            }
+            "sort" => self.sort = SortOrder::parse(value)?,
+            "list" => {
+                check_list_name(value)?;
+                self.list = value.to_string();
+            }
+            "color" => {
+                self.color = match value {
+                    "auto" => ColorMode::Auto,
+                    "always" => ColorMode::Always,
+                    "never" => ColorMode::Never,
+                    _ => {
+                        return Err(format!(
+                            "Unknown color {}, use auto, always or never",
+                            value
+                        ))
+                    }
+                }
+            }
+            "overdue_color" => self.table.overdue_color = parse_color_or_error(value)?,
+            "today_color" => self.table.today_color = parse_color_or_error(value)?,
+            "columns" => self.table.columns = parse_columns(value)?,
+            "date_format" => {
+                check_format(value)?;
+                self.table.date_format = value.to_string();
+            }
+            "time_format" => {
+                check_format(value)?;
+                self.table.time_format = value.to_string();
+            }
+            "wip" => self.wip_limits = parse_wip_limits(value)?,
+            _ => return Err(format!("Unknown config key {}", key)),
+        }
+        self.values.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    // The value of a key as written in the config file, or its default.
+    pub fn get(&self, key: &str) -> Result<String, String> {
+        match KEYS.iter().find(|(k, _)| *k == key) {
+            Some((k, default)) => Ok(self
+                .values
+                .get(*k)
+                .cloned()
+                .unwrap_or_else(|| default.to_string())),
+            None => Err(format!("Unknown config key {}", key)),
+        }
+    }
+
+    pub fn keys() -> impl Iterator<Item = &'static str> {
+        KEYS.iter().map(|(k, _)| *k)
+    }
+
+    pub fn use_color(&self) -> bool {
+        match self.color {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal(),
+        }
+    }
+}
+
+// $XDG_CONFIG_HOME/todo/config, ~/.config/todo/config without it. The old
+// ~/.todo/config is used as long as only it exists.
+pub fn config_file_path(home_dir: &Path) -> PathBuf {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(v) if !v.is_empty() => PathBuf::from(v),
+        _ => home_dir.join(".config"),
+    };
+    let path = config_home.join("todo").join("config");
+    let legacy_path = home_dir.join(".todo").join("config");
+    if !path.exists() && legacy_path.exists() {
+        legacy_path
+    } else {
+        path
+    }
+}
+
+// A leading ~ in paths from the config file stands for the home directory.
+pub fn expand_home(path: &str, home_dir: &Path) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some("") => home_dir.to_path_buf(),
+        Some(v) if v.starts_with('/') => home_dir.join(&v[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn read_config_file(config_path: &Path) -> Result<String, TodoIOError> {
+    match fs::read_to_string(config_path) {
+        Ok(v) => Ok(v),
+        Err(err) => match err.kind() {
+            ErrorKind::NotFound => Ok(String::new()),
+            _ => Err(TodoIOError::new(&format!(
+                "Error reading config file: {}",
+                err
+            ))),
+        },
+    }
+}
+
+// The key of a `key = value` line, None for comments and empty lines.
+fn line_key(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    line.split_once('=').map(|(key, _)| key.trim())
+}
+
+// The config file consists of `key = value` lines, `#` starts a comment.
+pub fn load_config(config_path: &Path) -> Result<Config, TodoIOError> {
+    let mut config = Config::default();
+    let content = read_config_file(config_path)?;
+
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = match line.split_once('=') {
+            Some((key, value)) => config.set(key.trim(), value.trim()),
+            None => Err(String::from("Expected key = value")),
+        };
+        if let Err(err) = result {
+            return Err(TodoIOError::new(&format!(
+                "{}:{}: {}",
+                config_path.display(),
+                line_number + 1,
+                err
+            )));
+        }
+    }
+
+    Ok(config)
+}
+
+// Replaces the line of the key, or appends one. Comments and the other
+// keys stay as they are.
+pub fn set_config_value(config_path: &Path, key: &str, value: &str) -> Result<(), TodoIOError> {
+    if let Err(err) = Config::default().set(key, value) {
+        return Err(TodoIOError::new(&err));
+    }
+
+    let content = read_config_file(config_path)?;
+    let new_line = format!("{} = {}", key, value);
+    let mut lines: Vec<String> = Vec::new();
+    let mut replaced = false;
+    for line in content.lines() {
+        if line_key(line) == Some(key) {
+            if !replaced {
+                lines.push(new_line.clone());
+                replaced = true;
+            }
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    if !replaced {
+        lines.push(new_line);
+    }
+
+    if let Some(parent) = config_path.parent() {
+        if let Err(err) = fs::create_dir_all(parent) {
+            return Err(TodoIOError::new(&format!(
+                "Error creating config directory: {}",
+                err
+            )));
+        }
+    }
+    if let Err(err) = write_atomic(config_path, format!("{}\n", lines.join("\n")).as_bytes()) {
+        return Err(TodoIOError::new(&format!(
+            "Error writing config file: {}",
+            err
+        )));
+    }
+    Ok(())
+}
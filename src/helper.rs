@@ -1,3 +1,70 @@
-pub fn print_todo_header() -> &'static str {
-    "ID\tStatus\tPrio\tDescription\tProjects\tCategories\tEstimated\tActual\tDifference\n"
+// Removes `--name value` (or `--name=value`) from args and returns the value.
+pub fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let prefix = format!("{}=", name);
+    for i in 0..args.len() {
+        if let Some(v) = args[i].strip_prefix(&prefix) {
+            let value = v.to_string();
+            args.remove(i);
+            return Ok(Some(value));
+        }
+        if args[i] == name {
+            if i + 1 >= args.len() {
+                return Err(format!("{} needs a value", name));
+            }
+            let value = args.remove(i + 1);
+            args.remove(i);
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+// Removes `--name` from args and returns whether it was there.
+pub fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+// Splits off the free text at the end of args, so options are only looked
+// for in front of it. The text starts after `--`, or at the first word that
+// is neither an option, the value of an option in `with_value`, nor one of
+// the `positional` arguments before it. --where stands in for the ids of a
+// selection, so it counts as one of them when it takes a value.
+pub fn split_free_text(
+    args: &mut Vec<String>,
+    positional: usize,
+    with_value: &[&str],
+) -> Vec<String> {
+    let mut count = 0;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == "--" {
+            let text = args.split_off(i + 1);
+            args.pop();
+            return text;
+        }
+        if arg.starts_with("--") {
+            let name = arg.split('=').next().unwrap();
+            if with_value.contains(&name) {
+                if name == "--where" {
+                    count += 1;
+                }
+                if !arg.contains('=') {
+                    i += 1;
+                }
+            }
+        } else if count < positional {
+            count += 1;
+        } else {
+            return args.split_off(i);
+        }
+        i += 1;
+    }
+    Vec::new()
 }
@@ -1,333 +1,1714 @@
+use chrono::{Local, NaiveDateTime, Timelike};
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::fs::OpenOptions;
 use std::io::ErrorKind;
+use std::io::IsTerminal;
+use std::io::Read;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use tabwriter::TabWriter;
 
+mod board;
+mod cli;
+mod config;
 mod helper;
+mod output;
+mod query;
+mod report;
+mod sort;
 mod structs;
 mod todoio;
-use self::helper::*;
+mod todotxt;
+mod tui;
+use self::board::*;
+use self::cli::*;
+use self::config::*;
+use self::output::*;
+use self::query::*;
+use self::report::*;
+use self::sort::*;
+use self::structs::deps::*;
+use self::structs::edit::*;
+use self::structs::parse::*;
+use self::structs::recur::*;
+use self::structs::tree::*;
 use self::structs::*;
 use self::todoio::*;
+use self::todotxt::*;
+use self::tui::*;
 
-fn add_new_todo(args: Vec<String>, id_file_path: &str, todo_file_path: &str) {
-    if args.len() < 3 {
-        print_help();
-        return;
-    }
+// Everything a command on the current list works with. `line` is the
+// command as typed, for the journal.
+struct Context {
+    line: String,
+    config: Config,
+    lists: Lists,
+    list: String,
+    store: Box<dyn TodoStore>,
+    journal: Journal,
+    lock_file_path: PathBuf,
+}
 
-    let current_id = match get_current_id(id_file_path) {
+// Records a finished command in the journal so it can be undone.
+fn journal_change(
+    line: &str,
+    id_before: usize,
+    before: Vec<Todo>,
+    after: Vec<Todo>,
+    store: &mut dyn TodoStore,
+    journal: &Journal,
+) {
+    let id_after = match store.peek_id() {
         Ok(v) => v,
         Err(err) => {
-            println!("Error getting new ID: {}", err);
+            eprintln!("Error writing journal: {}", err);
             return;
         }
     };
 
-    let mut new_todo = Todo::new_with_id(current_id);
-    if args.len() > 3 && new_todo.set_priority_from_string(&args[2]).is_ok() {
-        new_todo.set_description(&args[3..].join(" "));
-    } else {
-        new_todo.set_description(&args[2..].join(" "));
+    let entry = JournalEntry::new(line, id_before, id_after, before, after);
+    if entry.changes_nothing() {
+        return;
+    }
+    if let Err(err) = journal.record(&entry) {
+        eprintln!("Error writing journal: {}", err);
     }
+}
 
-    if let Err(err) = write_to_file(&new_todo.to_file(), todo_file_path) {
-        println!("Failed writing Todo to file: {}", err);
-    } else {
-        let mut tw = TabWriter::new(std::io::stdout()).padding(2);
-        tw.write_all("New Todo:\n".as_bytes()).unwrap();
-        tw.write_all(print_todo_header().as_bytes()).unwrap();
-        tw.write_all(format!("{}\n", new_todo).as_bytes()).unwrap();
-        tw.flush().unwrap();
+fn peek_id(store: &mut dyn TodoStore) -> Result<usize, String> {
+    store
+        .peek_id()
+        .map_err(|err| format!("Error reading ID: {}", err))
+}
+
+fn load_todos(store: &mut dyn TodoStore) -> Result<Vec<Todo>, String> {
+    store
+        .load()
+        .map_err(|err| format!("Reading Todos failed: {}", err))
+}
+
+fn get_todo(edit_id: usize, store: &mut dyn TodoStore) -> Result<Todo, String> {
+    match store.get(edit_id) {
+        Ok(Some(v)) => Ok(v),
+        Ok(None) => Err(format!("No Todo with ID {}", edit_id)),
+        Err(err) => Err(format!("Reading Todos failed: {}", err)),
     }
 }
 
-fn list_all_todos(args: Vec<String>, show_done: bool, todo_file_path: &str) {
-    let mut todos = match get_all_todos(todo_file_path) {
-        Ok(v) => v,
-        Err(err) => {
-            println!("Reading Todos failed: {}", err);
-            return;
-        }
-    };
-    todos.sort();
+fn write_failed(err: TodoIOError) -> String {
+    format!("Writing Todo file failed: {}", err)
+}
 
-    let mut search_string = String::from("");
-    if args.len() > 2 {
-        search_string = args[2..].join(" ");
+fn parse_query(s: &str, config: &Config) -> Result<Query, String> {
+    Query::parse(s, config.work_day).map_err(|err| err.to_string())
+}
+
+// Fills in the computed blocked state for the machine readable formats.
+fn refresh_blocked_from(todos: &mut [Todo], all_todos: &[Todo]) {
+    for todo in todos.iter_mut() {
+        todo.set_blocked(!open_dependencies(all_todos, todo).is_empty());
     }
+}
 
-    let mut tw = TabWriter::new(std::io::stdout()).padding(2);
-    tw.write_all(print_todo_header().as_bytes()).unwrap();
-    for todo in todos.iter() {
-        if (!todo.done() || todo.done() == show_done) && todo.filter(&search_string) {
-            tw.write_all(format!("{}\n", todo).as_bytes()).unwrap();
-        }
+fn add_new_todo(
+    ctx: &mut Context,
+    parent: Option<usize>,
+    raw: bool,
+    words: &[String],
+    format: OutputFormat,
+) -> Result<(), String> {
+    let store = ctx.store.as_mut();
+    if let Some(v) = parent {
+        get_todo(v, store)?;
     }
-    tw.flush().unwrap();
+
+    let mut new_todo = Todo::new();
+    new_todo.set_parent(parent);
+    let description = if words.len() > 1 && new_todo.set_priority_from_string(&words[0]).is_ok() {
+        words[1..].join(" ")
+    } else {
+        words.join(" ")
+    };
+    // +project @category !prio est: act: due: sched: and #color in the
+    // description fill in the fields, --raw keeps it as it is.
+    if raw {
+        new_todo.set_description(&description);
+    } else if let Err(err) = parse_quick_add(&mut new_todo, &description, ctx.config.work_day) {
+        return Err(format!("Error: {}", err));
+    }
+
+    let id_before = peek_id(store)?;
+    match store.next_id() {
+        Ok(v) => new_todo.set_id(v),
+        Err(err) => return Err(format!("Error getting new ID: {}", err)),
+    };
+
+    if let Err(err) = store.insert(&new_todo) {
+        return Err(format!("Failed writing Todo to file: {}", err));
+    }
+    let mut printer = TodoPrinter::new(format, &ctx.config.table);
+    printer.heading("New Todo:");
+    printer.header();
+    printer.push(&new_todo);
+
+    journal_change(
+        &ctx.line,
+        id_before,
+        vec![],
+        vec![new_todo.clone()],
+        store,
+        &ctx.journal,
+    );
+
+    printer.flush().unwrap();
+    Ok(())
 }
 
-fn set_todo(args: Vec<String>, todo_file_path: &str) {
-    let edit_id = match args[3].parse::<usize>() {
-        Ok(v) => v,
-        Err(err) => {
-            println!("Error: {}", err);
-            print_help();
-            return;
-        }
+fn list_all_todos(
+    ctx: &mut Context,
+    show_done: bool,
+    ready_only: bool,
+    all_lists: bool,
+    sort: Option<&SortOrder>,
+    query: &str,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let config = &ctx.config;
+    let sort = sort.unwrap_or(&config.sort);
+    let query = parse_query(query, config)?;
+    let names = if all_lists {
+        ctx.lists.names().map_err(|err| format!("Error: {}", err))?
+    } else {
+        vec![ctx.list.clone()]
     };
 
-    let mut todos = match get_all_todos(todo_file_path) {
-        Ok(v) => v,
-        Err(err) => {
-            println!("Reading Todos failed: {}", err);
-            return;
-        }
+    let mut printer = if all_lists {
+        TodoPrinter::with_lists(format, &config.table)
+    } else {
+        TodoPrinter::new(format, &config.table)
     };
+    printer.header();
+    for name in names {
+        let loaded = if name == ctx.list {
+            ctx.store.load()
+        } else {
+            ctx.lists.open(&name, config).and_then(|mut v| v.load())
+        };
+        let mut todos = loaded.map_err(|err| format!("Reading Todos failed: {}", err))?;
+        refresh_blocked(&mut todos);
+        sort.sort(&mut todos);
 
-    let mut tw = TabWriter::new(std::io::stdout()).padding(2);
-    tw.write_all(print_todo_header().as_bytes()).unwrap();
+        let visible: Vec<&Todo> = todos
+            .iter()
+            .filter(|todo| (!todo.done() || todo.done() == show_done) && query.matches(todo))
+            .filter(|todo| !ready_only || !todo.is_blocked())
+            .collect();
 
-    if let Err(err) = truncate_file(todo_file_path) {
-        println!("Writing Todo file failed: {}", err);
-        return;
+        printer.set_list(&name);
+        for (todo, depth) in tree_order(&visible) {
+            printer.push_shown(todo, &tree_display_todo(todo, depth, &todos));
+        }
     }
-    for todo in todos.iter_mut() {
-        if todo.get_id() == edit_id {
-            match &args[2][..] {
-                "prio" => {
-                    if let Err(err) = todo.set_priority_from_string(&args[4]) {
-                        println!("Error setting priority: {}", err)
-                    }
-                }
-                "desc" => todo.set_description(&args[4]),
-                "proj" => todo.set_projects(&args[4]),
-                "cat" => todo.set_categories(&args[4]),
-                "est" => {
-                    if let Err(err) = todo.set_time_estimated_from_string(&args[4]) {
-                        println!("Error setting time estimated: {}", err)
+    printer.flush().unwrap();
+    Ok(())
+}
+
+// The ids a command works on. Every listed id has to exist, a query
+// matches done todos as well.
+fn select_ids(
+    selection: &Selection,
+    todos: &[Todo],
+    config: &Config,
+) -> Result<Vec<usize>, String> {
+    match selection {
+        Selection::Ids(ranges) => {
+            // Every id has to exist, so a range stops at the first missing
+            // one and never grows beyond the number of todos.
+            let existing: HashSet<usize> = todos.iter().map(|todo| todo.get_id()).collect();
+            let mut seen = HashSet::new();
+            let mut ids = Vec::new();
+            for range in ranges {
+                for id in range.clone() {
+                    if !existing.contains(&id) {
+                        return Err(format!("No Todo with ID {}", id));
                     }
-                }
-                "act" => {
-                    if let Err(err) = todo.set_time_actual_from_string(&args[4]) {
-                        println!("Error setting time actual: {}", err)
+                    if seen.insert(id) {
+                        ids.push(id);
                     }
                 }
-                "stat" => {
-                    if let Err(err) = todo.set_status_from_string(&args[4]) {
-                        println!("Error setting status: {}", err)
-                    }
-                }
-                "color" => todo.set_color_from_string(&args[4]),
-                &_ => {
-                    println!("No such attribute: {}", args[3]);
-                }
-            };
-            tw.write_all(format!("{}\n", todo).as_bytes()).unwrap();
+            }
+            Ok(ids)
         }
-        if let Err(err) = write_to_file(&todo.to_file(), todo_file_path) {
-            println!("Failed: {}", err);
+        Selection::Query(query) => {
+            let query = parse_query(query, config)?;
+            let ids: Vec<usize> = todos
+                .iter()
+                .filter(|todo| query.matches(todo))
+                .map(|todo| todo.get_id())
+                .collect();
+            if ids.is_empty() {
+                return Err(String::from("No Todo matches the query"));
+            }
+            Ok(ids)
         }
     }
-    tw.flush().unwrap();
 }
 
-fn rm_todo(args: Vec<String>, todo_file_path: &str, id_file_path: &str) {
-    if args[2] == "all" {
-        if let Err(err) = truncate_file(todo_file_path) {
-            println!("Writing Todo file failed: {}", err);
-            return;
-        }
-        let _ = set_current_id(0, id_file_path);
+// Commands on more than one todo, and dry runs, end with how many todos
+// they changed.
+fn print_summary(selection: Option<&Selection>, dry_run: bool, format: OutputFormat, text: &str) {
+    if format != OutputFormat::Table || (!dry_run && selection.is_some_and(|v| v.is_single())) {
         return;
     }
-    let edit_id = match args[2].parse::<usize>() {
+    if dry_run {
+        println!("{} (dry run, nothing was written)", text);
+    } else {
+        println!("{}", text);
+    }
+}
+
+fn set_attribute(
+    todo: &mut Todo,
+    attribute: Attribute,
+    value: &str,
+    todos: &[Todo],
+    config: &Config,
+) -> Result<(), String> {
+    match attribute {
+        Attribute::Priority => todo
+            .set_priority_from_string(value)
+            .map(|_| ())
+            .map_err(|err| format!("Error setting priority: {}", err)),
+        Attribute::Description => {
+            todo.set_description(value);
+            Ok(())
+        }
+        Attribute::Projects => {
+            todo.set_projects(value);
+            Ok(())
+        }
+        Attribute::Categories => {
+            todo.set_categories(value);
+            Ok(())
+        }
+        Attribute::Estimated => todo
+            .set_time_estimated_from_string(value, config.work_day)
+            .map(|_| ())
+            .map_err(|err| format!("Error setting time estimated: {}", err)),
+        Attribute::Actual => todo
+            .set_time_actual_from_string(value, config.work_day)
+            .map(|_| ())
+            .map_err(|err| format!("Error setting time actual: {}", err)),
+        Attribute::Status => todo
+            .set_status_from_string(value)
+            .map(|_| ())
+            .map_err(|err| format!("Error setting status: {}", err)),
+        Attribute::Color => {
+            todo.set_color_from_string(value);
+            Ok(())
+        }
+        Attribute::Due => todo
+            .set_due_from_string(value)
+            .map(|_| ())
+            .map_err(|err| format!("Error setting due date: {}", err)),
+        Attribute::Scheduled => todo
+            .set_scheduled_from_string(value)
+            .map(|_| ())
+            .map_err(|err| format!("Error setting scheduled date: {}", err)),
+        Attribute::Recurrence => todo
+            .set_recurrence_from_string(value)
+            .map(|_| ())
+            .map_err(|err| format!("Error setting recurrence: {}", err)),
+        Attribute::Parent => set_parent_checked(todo, value, todos)
+            .map_err(|err| format!("Error setting parent: {}", err)),
+    }
+}
+
+// Sets the attribute of every selected todo. A value that does not parse
+// leaves all of them as they are.
+fn set_todo(
+    ctx: &mut Context,
+    attribute: Attribute,
+    selection: &Selection,
+    value: &str,
+    dry_run: bool,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let config = &ctx.config;
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let mut todos = load_todos(store)?;
+    let ids = select_ids(selection, &todos, config)?;
+
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    for id in ids {
+        let index = todos.iter().position(|todo| todo.get_id() == id).unwrap();
+        let mut todo = todos[index].clone();
+        set_attribute(&mut todo, attribute, value, &todos, config)?;
+        // Later parents are checked against the new tree.
+        before.push(std::mem::replace(&mut todos[index], todo.clone()));
+        after.push(todo);
+    }
+    let changed = before
+        .iter()
+        .zip(after.iter())
+        .filter(|(b, a)| b.to_file() != a.to_file())
+        .count();
+
+    if !dry_run {
+        store.store_changes(&after, &[]).map_err(write_failed)?;
+        journal_change(
+            &ctx.line,
+            id_before,
+            before,
+            after.clone(),
+            store,
+            &ctx.journal,
+        );
+    }
+
+    if format != OutputFormat::Table {
+        refresh_blocked_from(&mut after, &todos);
+    }
+    let mut printer = TodoPrinter::new(format, &config.table);
+    printer.header();
+    for todo in after.iter() {
+        printer.push(todo);
+    }
+    printer.flush().unwrap();
+    let verb = if dry_run { "Would change" } else { "Changed" };
+    print_summary(
+        Some(selection),
+        dry_run,
+        format,
+        &format!("{} {} of {} Todos", verb, changed, after.len()),
+    );
+    Ok(())
+}
+
+// Sets the parent after making sure it exists and is not a subtask of the
+// todo itself. An empty value or "none" makes it a top level todo again.
+fn set_parent_checked(todo: &mut Todo, value: &str, todos: &[Todo]) -> Result<(), String> {
+    if value.is_empty() || value == "none" {
+        todo.set_parent(None);
+        return Ok(());
+    }
+
+    let parent = parse_usize(value).map_err(|err| err.to_string())?;
+    if !todos.iter().any(|t| t.get_id() == parent) {
+        return Err(format!("No Todo with ID {}", parent));
+    }
+    if would_create_cycle(todos, todo.get_id(), parent) {
+        return Err(format!("{} is a subtask of {}", parent, todo.get_id()));
+    }
+
+    todo.set_parent(Some(parent));
+    Ok(())
+}
+
+// Writes the file t edit opens. It lives in the todo directory and is
+// created new, so nobody can put a link to another file in its place, and
+// only the user can read it.
+fn create_edit_file(dir: &Path, text: &str) -> Result<PathBuf, String> {
+    let path = dir.join(format!("edit-{}.txt", std::process::id()));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|err| format!("Error writing {}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+// Runs $VISUAL or $EDITOR on the file, through the shell so the editor can
+// have arguments like code --wait.
+fn run_editor(path: &Path) -> Result<(), String> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|v| env::var(v).ok())
+        .find(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"));
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .map_err(|err| format!("Error running {}: {}", editor, err))?;
+    if !status.success() {
+        return Err(format!("{} failed ({}), nothing was saved", editor, status));
+    }
+    Ok(())
+}
+
+// Asks on the terminal, anything but n is a yes. Without a terminal the
+// answer is no.
+fn confirm(question: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    print!("{} [Y/n] ", question);
+    std::io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => false,
+        Ok(_) => !answer.trim().to_lowercase().starts_with('n'),
+    }
+}
+
+// Opens the editor until the file parses or the user gives up.
+fn edit_until_valid(path: &Path, todos: &[Todo], config: &Config) -> Result<Vec<Todo>, String> {
+    loop {
+        run_editor(path)?;
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Error reading {}: {}", path.display(), err))?;
+        match parse_edit_text(&text, todos, config.work_day) {
+            Ok(v) => return Ok(v),
+            Err(errors) => {
+                for err in errors.iter() {
+                    eprintln!("{}", err);
+                }
+                if !confirm("Edit again?") {
+                    return Err(String::from("Nothing was saved"));
+                }
+            }
+        }
+    }
+}
+
+// Edits all fields of the selected todos in the editor. The lock is only
+// taken to save, the todos must not have changed in the meantime.
+fn edit_todos(ctx: &mut Context, selection: &Selection) -> Result<(), String> {
+    let config = &ctx.config;
+    let todos = load_todos(ctx.store.as_mut())?;
+    let ids = select_ids(selection, &todos, config)?;
+    let originals: Vec<Todo> = ids
+        .iter()
+        .filter_map(|id| todos.iter().find(|todo| todo.get_id() == *id).cloned())
+        .collect();
+
+    let todo_dir = ctx.lock_file_path.parent().unwrap_or(Path::new("."));
+    let path = create_edit_file(todo_dir, &todos_to_edit_text(&originals))?;
+    let edited = edit_until_valid(&path, &todos, config);
+    let edited = match edited {
         Ok(v) => v,
         Err(err) => {
-            println!("Error: {}", err);
-            print_help();
-            return;
+            let _ = std::fs::remove_file(&path);
+            return Err(err);
         }
     };
+    let changed: Vec<Todo> = edited
+        .into_iter()
+        .filter(|todo| {
+            originals
+                .iter()
+                .any(|o| o.get_id() == todo.get_id() && o.to_file() != todo.to_file())
+        })
+        .collect();
+    if changed.is_empty() {
+        let _ = std::fs::remove_file(&path);
+        println!("Nothing changed");
+        return Ok(());
+    }
 
-    let mut todos = match get_all_todos(todo_file_path) {
-        Ok(v) => v,
-        Err(err) => {
-            println!("Reading Todos failed: {}", err);
-            return;
+    let _lock = lock_todo_files(&ctx.lock_file_path).map_err(|err| format!("Error: {}", err))?;
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let mut todos = load_todos(store)?;
+    for original in originals.iter() {
+        let unchanged = todos
+            .iter()
+            .any(|todo| todo.get_id() == original.get_id() && todo.to_file() == original.to_file());
+        if !unchanged {
+            return Err(format!(
+                "Todo {} was changed while editing, nothing was saved. Your edits are in {}",
+                original.get_id(),
+                path.display()
+            ));
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+
+    let mut before = Vec::new();
+    for todo in changed.iter() {
+        let index = todos.iter().position(|t| t == todo).unwrap();
+        before.push(std::mem::replace(&mut todos[index], todo.clone()));
+    }
+    store.store_changes(&changed, &[]).map_err(write_failed)?;
+    journal_change(
+        &ctx.line,
+        id_before,
+        before,
+        changed.clone(),
+        store,
+        &ctx.journal,
+    );
+
+    let mut printer = TodoPrinter::new(OutputFormat::Table, &config.table);
+    printer.header();
+    for todo in changed.iter() {
+        printer.push(todo);
+    }
+    printer.flush().unwrap();
+    print_summary(
+        Some(selection),
+        false,
+        OutputFormat::Table,
+        &format!("Changed {} of {} Todos", changed.len(), originals.len()),
+    );
+    Ok(())
+}
+
+// Appends a note to the todo, from the arguments or from stdin.
+fn add_note(ctx: &mut Context, edit_id: usize, text: Option<&str>) -> Result<(), String> {
+    let text = match text {
+        Some(v) => v.to_string(),
+        None => {
+            if std::io::stdin().is_terminal() {
+                return Err(String::from(
+                    "Missing note, give it as arguments or pipe it in",
+                ));
+            }
+            let mut v = String::new();
+            std::io::stdin()
+                .read_to_string(&mut v)
+                .map_err(|err| format!("Error reading the note: {}", err))?;
+            v
         }
     };
+    if text.trim().is_empty() {
+        return Err(String::from("The note is empty"));
+    }
+
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let mut todo = get_todo(edit_id, store)?;
+    let before = todo.clone();
+    todo.add_note(&text);
+    store.update(&[todo.clone()]).map_err(write_failed)?;
+    journal_change(
+        &ctx.line,
+        id_before,
+        vec![before],
+        vec![todo.clone()],
+        store,
+        &ctx.journal,
+    );
+
+    let todos = load_todos(store)?;
+    print!(
+        "{}",
+        todo_details(&todo, &todos, &ctx.config.table, Local::now().naive_local())
+    );
+    Ok(())
+}
+
+fn show_todo(ctx: &mut Context, edit_id: usize) -> Result<(), String> {
+    let todos = load_todos(ctx.store.as_mut())?;
+    let todo = match todos.iter().find(|todo| todo.get_id() == edit_id) {
+        Some(v) => v,
+        None => return Err(format!("No Todo with ID {}", edit_id)),
+    };
+    print!(
+        "{}",
+        todo_details(todo, &todos, &ctx.config.table, Local::now().naive_local())
+    );
+    Ok(())
+}
+
+// Removes the selected todos with their subtasks, or all of them.
+fn rm_todo(
+    ctx: &mut Context,
+    target: &RmTarget,
+    dry_run: bool,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let mut todos = load_todos(store)?;
+
+    let (ids, selection) = match target {
+        RmTarget::All => (todos.iter().map(|todo| todo.get_id()).collect(), None),
+        RmTarget::Todos(selection) => {
+            let ids = select_ids(selection, &todos, &ctx.config)?;
+            // Subtasks go together with their parent.
+            (with_descendants(&todos, &ids), Some(selection))
+        }
+    };
+    let before: Vec<Todo> = todos
+        .iter()
+        .filter(|todo| ids.contains(&todo.get_id()))
+        .cloned()
+        .collect();
+    todos.retain(|todo| !ids.contains(&todo.get_id()));
+
+    if !dry_run {
+        store.store_changes(&[], &ids).map_err(write_failed)?;
+        if todos.is_empty() && selection.is_none() {
+            let _ = store.set_next_id(0);
+        }
+        journal_change(
+            &ctx.line,
+            id_before,
+            before.clone(),
+            vec![],
+            store,
+            &ctx.journal,
+        );
+    }
+
+    let mut printer = TodoPrinter::new(format, &ctx.config.table);
+    printer.header();
+    for todo in before.iter() {
+        let mut todo = todo.clone();
+        todo.set_deleted();
+        printer.push(&todo);
+    }
+    printer.flush().unwrap();
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    print_summary(
+        selection,
+        dry_run,
+        format,
+        &format!("{} {} Todos", verb, before.len()),
+    );
+    Ok(())
+}
+
+fn clean(ctx: &mut Context) -> Result<(), String> {
+    let config = &ctx.config;
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let mut todos = load_todos(store)?;
+    let before = todos.clone();
 
     let mut tw = TabWriter::new(std::io::stdout()).padding(2);
-    tw.write_all(print_todo_header().as_bytes()).unwrap();
+    tw.write_all(config.table.header().as_bytes()).unwrap();
 
-    if let Err(err) = truncate_file(todo_file_path) {
-        println!("Writing Todo file failed: {}", err);
-        return;
+    let now = Local::now().naive_local();
+    SortOrder::default().sort_at(&mut todos, now);
+    let mut new_ids = HashMap::new();
+    for (new_id, todo) in todos.iter().enumerate() {
+        new_ids.insert(todo.get_id(), new_id);
     }
+    let new_id = todos.len();
     for todo in todos.iter_mut() {
-        if todo.get_id() != edit_id {
-            if let Err(err) = write_to_file(&todo.to_file(), todo_file_path) {
-                println!("Failed: {}", err);
+        todo.set_id(new_ids[&todo.get_id()]);
+        todo.set_parent(todo.get_parent().and_then(|v| new_ids.get(&v).copied()));
+        let depends = todo
+            .get_depends()
+            .iter()
+            .filter_map(|v| new_ids.get(v).copied())
+            .collect();
+        todo.set_depends(depends);
+
+        tw.write_all(format!("{}\n", config.table.row(todo, now)).as_bytes())
+            .unwrap();
+    }
+
+    // Only the todos whose line changed are written, the ids from new_id
+    // on are gone.
+    let unchanged: HashSet<String> = before.iter().map(|todo| todo.to_file()).collect();
+    let changed: Vec<Todo> = todos
+        .iter()
+        .filter(|todo| !unchanged.contains(&todo.to_file()))
+        .cloned()
+        .collect();
+    let removed: Vec<usize> = before
+        .iter()
+        .map(|todo| todo.get_id())
+        .filter(|id| *id >= new_id)
+        .collect();
+    store
+        .store_changes(&changed, &removed)
+        .map_err(write_failed)?;
+    if let Err(err) = store.set_next_id(new_id) {
+        eprintln!("Error resetting ID: {}", err);
+    }
+
+    journal_change(&ctx.line, id_before, before, todos, store, &ctx.journal);
+
+    tw.flush().unwrap();
+    Ok(())
+}
+
+// Marks the selected todos and their open subtasks as done. Recurring
+// todos come back as new ones.
+fn do_task(
+    ctx: &mut Context,
+    selection: &Selection,
+    dry_run: bool,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let mut todos = load_todos(store)?;
+    let selected = select_ids(selection, &todos, &ctx.config)?;
+
+    // Finishing a todo finishes its open subtasks as well.
+    let ids = with_descendants(&todos, &selected);
+    for id in selected.iter() {
+        let todo = todos.iter().find(|todo| todo.get_id() == *id).unwrap();
+        let open: Vec<usize> = open_dependencies(&todos, todo)
+            .into_iter()
+            .filter(|v| !ids.contains(v))
+            .collect();
+        if !todo.done() && !open.is_empty() {
+            println!(
+                "Warning: {} depends on {}, which is not done yet",
+                id,
+                usize_list_to_string(&open)
+            );
+        }
+    }
+
+    let before: Vec<Todo> = todos
+        .iter()
+        .filter(|todo| ids.contains(&todo.get_id()) && !todo.done())
+        .cloned()
+        .collect();
+    if before.is_empty() {
+        return Err(match selected[..] {
+            [id] => format!("Todo {} is already done", id),
+            _ => String::from("The Todos are already done"),
+        });
+    }
+
+    // New ids are counted here and stored once at the end.
+    let (mut after, next_instances) = finish_todos(&before, now_for_timer(), id_before);
+    let next_id = id_before + next_instances.len();
+    let done_count = after.len();
+    for todo in todos.iter_mut() {
+        if let Some(v) = after.iter().find(|v| v.get_id() == todo.get_id()) {
+            *todo = v.clone();
+        }
+    }
+    for todo in next_instances {
+        todos.push(todo.clone());
+        after.push(todo);
+    }
+
+    if !dry_run {
+        store.store_changes(&after, &[]).map_err(write_failed)?;
+        if next_id != id_before {
+            store
+                .set_next_id(next_id)
+                .map_err(|err| format!("Error setting ID: {}", err))?;
+        }
+        journal_change(
+            &ctx.line,
+            id_before,
+            before,
+            after.clone(),
+            store,
+            &ctx.journal,
+        );
+    }
+
+    if format != OutputFormat::Table {
+        refresh_blocked_from(&mut after, &todos);
+    }
+    let mut printer = TodoPrinter::new(format, &ctx.config.table);
+    printer.header();
+    for (i, todo) in after.iter().enumerate() {
+        if i == done_count {
+            printer.heading("Next:");
+            printer.header();
+        }
+        printer.push(todo);
+    }
+    printer.flush().unwrap();
+    let verb = if dry_run { "Would mark" } else { "Marked" };
+    print_summary(
+        Some(selection),
+        dry_run,
+        format,
+        &format!("{} {} Todos as done", verb, done_count),
+    );
+    Ok(())
+}
+
+// t dep (add|rm) <id> <on-id>: <id> can only start once <on-id> is done.
+fn dependency(
+    ctx: &mut Context,
+    action: DepAction,
+    edit_id: usize,
+    on_id: usize,
+) -> Result<(), String> {
+    let config = &ctx.config;
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let todos = load_todos(store)?;
+    let mut todo = match todos.iter().find(|todo| todo.get_id() == edit_id) {
+        Some(v) => v.clone(),
+        None => return Err(format!("No Todo with ID {}", edit_id)),
+    };
+    let before = todo.clone();
+
+    match action {
+        DepAction::Add => {
+            if !todos.iter().any(|todo| todo.get_id() == on_id) {
+                return Err(format!("No Todo with ID {}", on_id));
+            }
+            if would_create_dependency_cycle(&todos, edit_id, on_id) {
+                return Err(format!(
+                    "Error: {} already depends on {}, this would create a cycle",
+                    on_id, edit_id
+                ));
+            }
+            todo.add_dependency(on_id);
+        }
+        DepAction::Rm => {
+            if !todo.remove_dependency(on_id) {
+                return Err(format!("{} does not depend on {}", edit_id, on_id));
             }
-        } else {
-            todo.set_deleted();
-            tw.write_all(format!("{}\n", todo).as_bytes()).unwrap();
         }
     }
+
+    store.update(&[todo.clone()]).map_err(write_failed)?;
+
+    let mut all_todos = todos;
+    all_todos.retain(|t| t.get_id() != edit_id);
+    all_todos.push(todo.clone());
+    let mut tw = TabWriter::new(std::io::stdout()).padding(2);
+    tw.write_all(config.table.header().as_bytes()).unwrap();
+    tw.write_all(
+        format!(
+            "{}\n",
+            config.table.row(
+                &tree_display_todo(&todo, 0, &all_todos),
+                Local::now().naive_local()
+            )
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    journal_change(
+        &ctx.line,
+        id_before,
+        vec![before],
+        vec![todo],
+        store,
+        &ctx.journal,
+    );
+
     tw.flush().unwrap();
+    Ok(())
 }
 
-fn clean(todo_file_path: &str, id_file: &str) {
-    let mut todos = match get_all_todos(todo_file_path) {
-        Ok(v) => v,
-        Err(err) => {
-            println!("Reading Todos failed: {}", err);
-            return;
-        }
+fn now_for_timer() -> NaiveDateTime {
+    let now = Local::now().naive_local();
+    now.with_nanosecond(0).unwrap_or(now)
+}
+
+fn start_timer(ctx: &mut Context, edit_id: usize) -> Result<(), String> {
+    let config = &ctx.config;
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let todos = load_todos(store)?;
+    let mut todo = match todos.iter().find(|todo| todo.get_id() == edit_id) {
+        Some(v) => v.clone(),
+        None => return Err(format!("No Todo with ID {}", edit_id)),
     };
+    let before = todo.clone();
+
+    if let Err(err) = todo.start_session(now_for_timer()) {
+        return Err(format!("Error starting timer: {}", err));
+    }
+    for other in todos.iter() {
+        if let Some(session) = other.running_session() {
+            println!(
+                "Warning: timer for {} is running as well since {}, stop it with t stop {}",
+                other.get_id(),
+                config.table.date(session.start),
+                other.get_id()
+            );
+        }
+    }
+
+    store.update(&[todo.clone()]).map_err(write_failed)?;
 
     let mut tw = TabWriter::new(std::io::stdout()).padding(2);
-    tw.write_all(print_todo_header().as_bytes()).unwrap();
+    tw.write_all("Started:\n".as_bytes()).unwrap();
+    tw.write_all(config.table.header().as_bytes()).unwrap();
+    let row = config.table.row(&todo, Local::now().naive_local());
+    tw.write_all(format!("{}\n", row).as_bytes()).unwrap();
 
-    if let Err(err) = truncate_file(todo_file_path) {
-        println!("Writing Todo file failed: {}", err);
-        return;
+    journal_change(
+        &ctx.line,
+        id_before,
+        vec![before],
+        vec![todo],
+        store,
+        &ctx.journal,
+    );
+
+    tw.flush().unwrap();
+    Ok(())
+}
+
+// Stops the timer of the given todo, or every running timer.
+fn stop_timer(ctx: &mut Context, edit_id: Option<usize>) -> Result<(), String> {
+    let config = &ctx.config;
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let todos = load_todos(store)?;
+
+    let now = now_for_timer();
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    for todo in todos.iter() {
+        if edit_id.is_some() && edit_id != Some(todo.get_id()) {
+            continue;
+        }
+        let mut stopped = todo.clone();
+        if stopped.stop_session(now).is_some() {
+            before.push(todo.clone());
+            after.push(stopped);
+        }
     }
 
-    todos.sort();
-    let mut new_id: usize = 0;
-    for todo in todos.iter_mut() {
-        todo.set_id(new_id);
-        new_id += 1;
+    if after.is_empty() {
+        return Err(match edit_id {
+            Some(v) => format!("No timer running for {}", v),
+            None => String::from("No timer running"),
+        });
+    }
 
-        if let Err(err) = write_to_file(&todo.to_file(), todo_file_path) {
-            println!("Failed: {}", err);
+    store.update(&after).map_err(write_failed)?;
+
+    let mut tw = TabWriter::new(std::io::stdout()).padding(2);
+    tw.write_all("Stopped:\n".as_bytes()).unwrap();
+    tw.write_all(config.table.header().as_bytes()).unwrap();
+    for todo in after.iter() {
+        tw.write_all(format!("{}\n", config.table.row(todo, now)).as_bytes())
+            .unwrap();
+    }
+
+    journal_change(&ctx.line, id_before, before, after, store, &ctx.journal);
+
+    tw.flush().unwrap();
+    Ok(())
+}
+
+fn timer_status(ctx: &mut Context) -> Result<(), String> {
+    let todos = load_todos(ctx.store.as_mut())?;
+
+    let now = now_for_timer();
+    let mut tw = TabWriter::new(std::io::stdout()).padding(2);
+    tw.write_all("ID\tDescription\tSince\tRunning\tActual\n".as_bytes())
+        .unwrap();
+    let mut running = 0;
+    for todo in todos.iter() {
+        if let Some(session) = todo.running_session() {
+            running += 1;
+            let elapsed = session.duration(now);
+            let total = todo.get_time_actual().unwrap_or_default() + elapsed;
+            tw.write_all(
+                format!(
+                    "{}\t{}\t{}\t{}\t{}\n",
+                    todo.get_id(),
+                    todo.get_description(),
+                    ctx.config.table.date(session.start),
+                    duration_to_human_string(elapsed),
+                    duration_to_human_string(total),
+                )
+                .as_bytes(),
+            )
+            .unwrap();
         }
-        tw.write_all(format!("{}\n", todo).as_bytes()).unwrap();
+    }
+
+    if running == 0 {
+        println!("No timer running");
+        return Ok(());
     }
     tw.flush().unwrap();
+    Ok(())
+}
 
-    if let Err(err) = set_current_id(new_id, id_file) {
-        println!("Error resetting ID: {}", err);
+fn report(
+    ctx: &mut Context,
+    from: Option<&str>,
+    to: Option<&str>,
+    grouping: ReportGrouping,
+    csv: bool,
+    filter: &str,
+) -> Result<(), String> {
+    // Both ends are whole days unless a time is given, --to includes its day.
+    let mut range = ReportRange::default();
+    if let Some(from) = from {
+        match parse_date_input(from) {
+            Ok(v) => range.from = v,
+            Err(err) => return Err(format!("Error in --from: {}", err)),
+        }
+    }
+    if let Some(to) = to {
+        match parse_date_input(to) {
+            Ok(Some(v)) if is_date_only(v) => range.to = Some(v + chrono::Duration::days(1)),
+            Ok(v) => range.to = v,
+            Err(err) => return Err(format!("Error in --to: {}", err)),
+        }
     }
+
+    let query = parse_query(filter, &ctx.config)?;
+    let todos: Vec<Todo> = load_todos(ctx.store.as_mut())?
+        .into_iter()
+        .filter(|todo| query.matches(todo))
+        .collect();
+
+    let report = build_time_report(&todos, grouping, &range, now_for_timer());
+    let mut stdout = std::io::stdout();
+    let result = if csv {
+        write_report_csv(&report, &mut stdout)
+    } else {
+        write_report_table(&report, &mut stdout)
+    };
+    result.map_err(|err| format!("Error writing report: {}", err))
 }
 
-fn do_task(args: Vec<String>, todo_file_path: &str) {
-    let edit_id = match args[2].parse::<usize>() {
-        Ok(v) => v,
-        Err(err) => {
-            println!("Error: {}", err);
-            print_help();
-            return;
+// t board: the todos that match the query in a column per status.
+fn board(
+    ctx: &mut Context,
+    lanes: Option<BoardLanes>,
+    show_done: bool,
+    query: &str,
+) -> Result<(), String> {
+    let config = &ctx.config;
+    let query = parse_query(query, config)?;
+    let mut todos = load_todos(ctx.store.as_mut())?;
+    config.sort.sort(&mut todos);
+    todos.retain(|todo| query.matches(todo));
+
+    let style = BoardStyle {
+        width: output_width(),
+        color: config.table.color,
+        warn_color: config.table.overdue_color,
+        wip_limits: config.wip_limits.clone(),
+    };
+    let (board, warnings) = render_board(&todos, lanes, show_done, &style);
+    print!("{}", board);
+    for warning in warnings {
+        println!("{}", warning);
+    }
+    Ok(())
+}
+
+// t export todotxt: prints every todo as a todo.txt line.
+fn export(ctx: &mut Context) -> Result<(), String> {
+    let mut todos = load_todos(ctx.store.as_mut())?;
+    todos.sort_by_key(|todo| todo.get_id());
+
+    let mut stdout = std::io::stdout();
+    for todo in todos.iter() {
+        if let Err(err) = writeln!(stdout, "{}", todo_to_todotxt(todo)) {
+            if err.kind() != ErrorKind::BrokenPipe {
+                return Err(format!("Error writing export: {}", err));
+            }
+            return Ok(());
         }
+    }
+    Ok(())
+}
+
+// t import todotxt <file>: adds the todos of a todo.txt file, - reads stdin.
+// The ids of the file are kept if they are all free, otherwise the todos
+// get new ones and parent and dep are changed to match.
+fn import(ctx: &mut Context, path: &str) -> Result<(), String> {
+    let content = if path == "-" {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(path)
     };
+    let content = content.map_err(|err| format!("Error reading {}: {}", path, err))?;
 
-    let mut todos = match get_all_todos(todo_file_path) {
-        Ok(v) => v,
-        Err(err) => {
-            println!("Reading Todos failed: {}", err);
-            return;
+    let mut imported = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_todotxt_line(line, ctx.config.work_day) {
+            Ok(v) => imported.push(v),
+            Err(err) => return Err(format!("Error in {}:{}: {}", path, line_number + 1, err)),
+        }
+    }
+
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let todos = load_todos(store)?;
+
+    let file_ids: Vec<Option<usize>> = imported.iter().map(|(_, id)| *id).collect();
+    let keep_ids = file_ids.iter().enumerate().all(|(i, id)| match id {
+        Some(id) => !file_ids[..i].contains(&Some(*id)) && !todos.iter().any(|t| t.get_id() == *id),
+        None => false,
+    });
+
+    let mut new_ids = HashMap::new();
+    for (todo, file_id) in imported.iter_mut() {
+        let id = match file_id {
+            Some(v) if keep_ids => *v,
+            _ => match store.next_id() {
+                Ok(v) => v,
+                Err(err) => return Err(format!("Error getting new ID: {}", err)),
+            },
+        };
+        if let Some(v) = file_id {
+            new_ids.insert(*v, id);
+        }
+        todo.set_id(id);
+    }
+
+    let mut imported: Vec<Todo> = imported.into_iter().map(|(todo, _)| todo).collect();
+    if keep_ids {
+        let next_id = imported.iter().map(|t| t.get_id() + 1).max().unwrap_or(0);
+        if let Err(err) = store.set_next_id(next_id.max(id_before)) {
+            return Err(format!("Error setting ID: {}", err));
+        }
+    } else {
+        // References to todos outside of the file cannot be kept.
+        for todo in imported.iter_mut() {
+            todo.set_parent(todo.get_parent().and_then(|v| new_ids.get(&v).copied()));
+            let depends = todo
+                .get_depends()
+                .iter()
+                .filter_map(|v| new_ids.get(v).copied())
+                .collect();
+            todo.set_depends(depends);
+        }
+    }
+
+    store.store_changes(&imported, &[]).map_err(write_failed)?;
+
+    let count = imported.len();
+    journal_change(&ctx.line, id_before, vec![], imported, store, &ctx.journal);
+    println!("Imported {} Todos", count);
+    Ok(())
+}
+
+// t list ls shows every list with its number of open and done todos, the
+// current one marked with *. t list new <name> creates a list.
+fn list_command(
+    action: &ListAction,
+    lists: &Lists,
+    list: &str,
+    config: &Config,
+) -> Result<(), String> {
+    match action {
+        ListAction::Ls => {
+            let names = lists.names().map_err(|err| format!("Error: {}", err))?;
+            let mut tw = TabWriter::new(std::io::stdout()).padding(2);
+            tw.write_all("\tList\tOpen\tDone\n".as_bytes()).unwrap();
+            for name in names {
+                let todos = match lists.open(&name, config).and_then(|mut v| v.load()) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        return Err(format!("Reading Todos of list {} failed: {}", name, err))
+                    }
+                };
+                let done = todos.iter().filter(|todo| todo.done()).count();
+                let marker = if name == list { "*" } else { "" };
+                tw.write_all(
+                    format!("{}\t{}\t{}\t{}\n", marker, name, todos.len() - done, done).as_bytes(),
+                )
+                .unwrap();
+            }
+            tw.flush().unwrap();
+        }
+        ListAction::New(name) => {
+            lists
+                .create(name)
+                .map_err(|err| format!("Error: {}", err))?;
+            println!("Created list {}", name);
         }
+    }
+    Ok(())
+}
+
+// Moves a todo and its subtasks to another list, where they get new ids.
+// Parents and dependencies outside of the moved todos are dropped. The
+// journal of this list records both halves, so undo takes back the move.
+fn move_todo(
+    ctx: &mut Context,
+    edit_id: usize,
+    target: &str,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let config = &ctx.config;
+    let lists = &ctx.lists;
+    let list = &ctx.list;
+    if target == list {
+        return Err(format!("Todo {} is already in list {}", edit_id, list));
+    }
+    if !lists.exists(target) {
+        return Err(format!(
+            "No list named {}, create it with t list new {}",
+            target, target
+        ));
+    }
+
+    let store = ctx.store.as_mut();
+    let id_before = peek_id(store)?;
+    let todos = load_todos(store)?;
+    if !todos.iter().any(|todo| todo.get_id() == edit_id) {
+        return Err(format!("No Todo with ID {}", edit_id));
+    }
+    let mut ids = vec![edit_id];
+    ids.extend(descendant_ids(&todos, edit_id));
+    let before: Vec<Todo> = todos
+        .into_iter()
+        .filter(|todo| ids.contains(&todo.get_id()))
+        .collect();
+
+    let mut target_store = lists
+        .open(target, config)
+        .map_err(|err| format!("Error: {}", err))?;
+    let target_store = target_store.as_mut();
+    let target_id_before = peek_id(target_store)?;
+    let mut new_ids = HashMap::new();
+    for todo in before.iter() {
+        match target_store.next_id() {
+            Ok(v) => new_ids.insert(todo.get_id(), v),
+            Err(err) => return Err(format!("Error reading ID: {}", err)),
+        };
+    }
+    let mut after = Vec::new();
+    for todo in before.iter() {
+        let mut todo = todo.clone();
+        todo.set_id(new_ids[&todo.get_id()]);
+        todo.set_parent(todo.get_parent().and_then(|v| new_ids.get(&v).copied()));
+        let depends = todo
+            .get_depends()
+            .iter()
+            .filter_map(|v| new_ids.get(v).copied())
+            .collect();
+        todo.set_depends(depends);
+        after.push(todo);
+    }
+
+    // The todos are written to the target first, a failure afterwards
+    // leaves them in both lists instead of none.
+    target_store
+        .store_changes(&after, &[])
+        .map_err(write_failed)?;
+    store.delete(&ids).map_err(write_failed)?;
+
+    // One entry in this list, t undo takes the todos out of the target
+    // list again.
+    let mut entry = JournalEntry::new(&ctx.line, id_before, id_before, before, vec![]);
+    entry.moved_to = Some(MovedTo {
+        list: target.to_string(),
+        id_before: target_id_before,
+        id_after: target_id_before + after.len(),
+        todos: after.clone(),
+    });
+    if let Err(err) = ctx.journal.record(&entry) {
+        eprintln!("Error writing journal: {}", err);
+    }
+
+    let mut printer = TodoPrinter::new(format, &config.table);
+    printer.heading(&format!("Moved to {}:", target));
+    printer.header();
+    for todo in after.iter() {
+        printer.push(todo);
+    }
+    printer.flush().unwrap();
+    Ok(())
+}
+
+// t config lists all keys with their values, t config get <key> shows one
+// and t config set <key> <value> writes it to the config file.
+fn config_command(
+    action: &ConfigAction,
+    config: &Config,
+    config_file_path: &Path,
+) -> Result<(), String> {
+    match action {
+        ConfigAction::List => {
+            for key in Config::keys() {
+                println!("{} = {}", key, config.get(key).unwrap());
+            }
+        }
+        ConfigAction::Get(key) => {
+            let value = config.get(key).map_err(|err| format!("Error: {}", err))?;
+            println!("{}", value);
+        }
+        ConfigAction::Set(key, value) => {
+            set_config_value(config_file_path, key, value)
+                .map_err(|err| format!("Error: {}", err))?;
+            println!("{} = {}", key, value);
+        }
+    }
+    Ok(())
+}
+
+// The list a todo was moved to, with its todos.
+fn open_moved_to(
+    ctx: &Context,
+    moved_to: &MovedTo,
+) -> Result<(Box<dyn TodoStore>, Vec<Todo>), String> {
+    let mut store = ctx
+        .lists
+        .open(&moved_to.list, &ctx.config)
+        .map_err(|err| format!("Error: {}", err))?;
+    let todos = store
+        .load()
+        .map_err(|err| format!("Reading Todos of list {} failed: {}", moved_to.list, err))?;
+    Ok((store, todos))
+}
+
+fn undo(ctx: &mut Context) -> Result<(), String> {
+    let mut state = ctx
+        .journal
+        .load()
+        .map_err(|err| format!("Reading journal failed: {}", err))?;
+    let entry = match state.done.pop() {
+        Some(v) => v,
+        None => return Err(String::from("Nothing to undo")),
     };
 
-    let mut tw = TabWriter::new(std::io::stdout()).padding(2);
-    tw.write_all(print_todo_header().as_bytes()).unwrap();
+    // A move is only taken back while the moved todos are as they were.
+    let mut target = None;
+    if let Some(moved_to) = &entry.moved_to {
+        let (store, todos) = open_moved_to(ctx, moved_to)?;
+        for todo in moved_to.todos.iter() {
+            if !todos.iter().any(|v| v.to_file() == todo.to_file()) {
+                return Err(format!(
+                    "Todo {} in list {} changed after the move, undo that there first",
+                    todo.get_id(),
+                    moved_to.list
+                ));
+            }
+        }
+        target = Some(store);
+    }
 
-    if let Err(err) = truncate_file(todo_file_path) {
-        println!("Writing Todo file failed: {}", err);
-        return;
+    // The todos come back here before they leave the other list, a failure
+    // in between leaves them in both.
+    if let Err(err) = apply_journal_entry(
+        ctx.store.as_mut(),
+        &entry.after,
+        &entry.before,
+        entry.id_before,
+    ) {
+        return Err(format!("Undo failed: {}", err));
     }
-    for todo in todos.iter_mut() {
-        if todo.get_id() == edit_id {
-            todo.set_status(TodoStatus::Done).unwrap();
-            tw.write_all(format!("{}\n", todo).as_bytes()).unwrap();
+    if let (Some(moved_to), Some(mut store)) = (&entry.moved_to, target) {
+        // The next id only goes back when the list got no todos since.
+        let next_id = match peek_id(store.as_mut())? {
+            v if v == moved_to.id_after => moved_to.id_before,
+            v => v,
+        };
+        if let Err(err) = apply_journal_entry(store.as_mut(), &moved_to.todos, &[], next_id) {
+            return Err(format!("Undo failed in list {}: {}", moved_to.list, err));
         }
-        if let Err(err) = write_to_file(&todo.to_file(), todo_file_path) {
-            println!("Failed: {}", err);
+    }
+    if let Err(err) = ctx.journal.mark_undone() {
+        return Err(format!("Error writing journal: {}", err));
+    }
+    println!("Undone: t {}", entry.command);
+    Ok(())
+}
+
+fn redo(ctx: &mut Context) -> Result<(), String> {
+    let mut state = ctx
+        .journal
+        .load()
+        .map_err(|err| format!("Reading journal failed: {}", err))?;
+    let entry = match state.undone.pop() {
+        Some(v) => v,
+        None => return Err(String::from("Nothing to redo")),
+    };
+
+    // A move writes the todos to the other list first, as t move does.
+    if let Some(moved_to) = &entry.moved_to {
+        let (mut store, todos) = open_moved_to(ctx, moved_to)?;
+        let taken = todos
+            .iter()
+            .find(|todo| moved_to.todos.iter().any(|v| v.get_id() == todo.get_id()));
+        if let Some(todo) = taken {
+            return Err(format!(
+                "List {} has a new Todo with ID {}, the move cannot be redone",
+                moved_to.list,
+                todo.get_id()
+            ));
+        }
+        let next_id = peek_id(store.as_mut())?.max(moved_to.id_after);
+        if let Err(err) = apply_journal_entry(store.as_mut(), &[], &moved_to.todos, next_id) {
+            return Err(format!("Redo failed in list {}: {}", moved_to.list, err));
         }
     }
+
+    if let Err(err) = apply_journal_entry(
+        ctx.store.as_mut(),
+        &entry.before,
+        &entry.after,
+        entry.id_after,
+    ) {
+        return Err(format!("Redo failed: {}", err));
+    }
+    if let Err(err) = ctx.journal.mark_redone() {
+        return Err(format!("Error writing journal: {}", err));
+    }
+    println!("Redone: t {}", entry.command);
+    Ok(())
+}
+
+fn show_log(ctx: &mut Context, compact: bool) -> Result<(), String> {
+    let state = ctx
+        .journal
+        .load()
+        .map_err(|err| format!("Reading journal failed: {}", err))?;
+
+    if compact {
+        return ctx
+            .journal
+            .compact(&state)
+            .map_err(|err| format!("Compacting journal failed: {}", err));
+    }
+
+    let mut tw = TabWriter::new(std::io::stdout()).padding(2);
+    tw.write_all("Time\tState\tChanged\tCommand\n".as_bytes())
+        .unwrap();
+    for entry in state.undone.iter() {
+        write_log_entry(&mut tw, entry, "undone");
+    }
+    for entry in state.done.iter().rev() {
+        write_log_entry(&mut tw, entry, "done");
+    }
     tw.flush().unwrap();
+    Ok(())
 }
 
-fn print_help() {
-    println!(
-        r#"usage:
-t new [Prio] <description>
-t set (prio|desc|proj|cat|est|act|stat|color) <id> <value>
-t do  <id>
-t rm  <id>|all
-t ls  [searchterm]
-t lsa [searchterm]
-t clean # resets the ids
-
-Possible status:
-Open
-Backlog
-Next
-Planned
-Doing
-Review
-Done
-
-Colors:
-Black
-Red
-Green
-Yellow
-Blue
-Purple
-Cyan
-White
-"#
-    );
+fn write_log_entry(tw: &mut TabWriter<std::io::Stdout>, entry: &JournalEntry, state: &str) {
+    let changed = entry.before.len().max(entry.after.len());
+    tw.write_all(
+        format!(
+            "{}\t{}\t{}\tt {}\n",
+            entry.time.format("%Y-%m-%d %H:%M:%S"),
+            state,
+            changed,
+            entry.command
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+}
+
+fn run_command(ctx: &mut Context, command: &Command) -> Result<(), String> {
+    match command {
+        Command::New {
+            parent,
+            raw,
+            words,
+            format,
+        } => add_new_todo(ctx, *parent, *raw, words, *format),
+        Command::Set {
+            attribute,
+            selection,
+            value,
+            dry_run,
+            format,
+        } => set_todo(ctx, *attribute, selection, value, *dry_run, *format),
+        Command::Edit { selection } => edit_todos(ctx, selection),
+        Command::Note { id, text } => add_note(ctx, *id, text.as_deref()),
+        Command::Show { id } => show_todo(ctx, *id),
+        Command::Do {
+            selection,
+            dry_run,
+            format,
+        } => do_task(ctx, selection, *dry_run, *format),
+        Command::Rm {
+            target,
+            dry_run,
+            format,
+        } => rm_todo(ctx, target, *dry_run, *format),
+        Command::Ls {
+            show_done,
+            ready,
+            all_lists,
+            sort,
+            query,
+            format,
+        } => list_all_todos(
+            ctx,
+            *show_done,
+            *ready,
+            *all_lists,
+            sort.as_ref(),
+            query,
+            *format,
+        ),
+        Command::Dep { action, id, on } => dependency(ctx, *action, *id, *on),
+        Command::Clean => clean(ctx),
+        Command::Start { id } => start_timer(ctx, *id),
+        Command::Stop { id } => stop_timer(ctx, *id),
+        Command::Status => timer_status(ctx),
+        Command::Report {
+            from,
+            to,
+            grouping,
+            csv,
+            filter,
+        } => report(ctx, from.as_deref(), to.as_deref(), *grouping, *csv, filter),
+        Command::Export => export(ctx),
+        Command::Import { path } => import(ctx, path),
+        Command::Undo => undo(ctx),
+        Command::Redo => redo(ctx),
+        Command::Log { compact } => show_log(ctx, *compact),
+        Command::Move { id, list, format } => move_todo(ctx, *id, list, *format),
+        Command::Board {
+            lanes,
+            show_done,
+            query,
+        } => board(ctx, *lanes, *show_done, query),
+        Command::Tui => run_tui(
+            ctx.store.as_mut(),
+            &ctx.journal,
+            &ctx.config,
+            &ctx.lock_file_path,
+        ),
+        // Handled in main, they do not need a list.
+        Command::List(_) | Command::Config(_) | Command::Help { .. } => Ok(()),
+    }
 }
 
-fn main() {
+fn run(cli: Cli) -> Result<(), String> {
     let user_home_dir = match home::home_dir() {
         Some(v) => v,
-        None => {
-            println!("Error. No home dir found. Exiting");
-            return;
-        }
+        None => return Err(String::from("Error. No home dir found. Exiting")),
     };
-    let user_home_dir = match user_home_dir.to_str() {
-        Some(v) => v,
-        None => {
-            println!("Error. No home dir found. Exiting");
-            return;
+
+    let config_file_path = config_file_path(&user_home_dir);
+    let mut config = load_config(&config_file_path).map_err(|err| format!("Error: {}", err))?;
+    config.table.color = config.use_color();
+    if let Command::Config(action) = &cli.command {
+        return config_command(action, &config, &config_file_path);
+    }
+
+    // TODO_DIR and TODO_FILE override the config file, --file overrides both.
+    let todo_dir_path = match env::var_os("TODO_DIR") {
+        Some(v) if !v.is_empty() => PathBuf::from(v),
+        _ => expand_home(config.dir.as_deref().unwrap_or("~/.todo"), &user_home_dir),
+    };
+    let todo_file_path = cli.file.or_else(|| match env::var_os("TODO_FILE") {
+        Some(v) if !v.is_empty() => Some(PathBuf::from(v)),
+        _ => config
+            .file
+            .as_deref()
+            .map(|v| expand_home(v, &user_home_dir)),
+    });
+
+    if let Err(err) = std::fs::create_dir_all(&todo_dir_path) {
+        return Err(format!(
+            "Error. Todo directory could not be created: {}",
+            err
+        ));
+    }
+
+    let lock_file_path = todo_dir_path.join("lock");
+    let lists = Lists::new(&todo_dir_path, todo_file_path.as_deref());
+
+    // Everything that writes holds the lock from loading the todos until the
+    // new state is stored, so concurrent invocations cannot interleave.
+    let _lock = if cli.command.writes() {
+        Some(lock_todo_files(&lock_file_path).map_err(|err| format!("Error: {}", err))?)
+    } else {
+        None
+    };
+
+    let list = cli.list.unwrap_or_else(|| config.list.clone());
+    // t list does not need a store and has to work when the configured list
+    // is missing, to create it.
+    if let Command::List(action) = &cli.command {
+        return list_command(action, &lists, &list, &config);
+    }
+    if !lists.exists(&list) {
+        return Err(format!(
+            "No list named {}, create it with t list new {}",
+            list, list
+        ));
+    }
+    let journal = lists.journal(&list);
+    let store = lists
+        .open(&list, &config)
+        .map_err(|err| format!("Error: {}", err))?;
+
+    let mut ctx = Context {
+        line: cli.line,
+        config,
+        lists,
+        list,
+        store,
+        journal,
+        lock_file_path,
+    };
+    run_command(&mut ctx, &cli.command)
+}
+
+fn main() -> ExitCode {
+    let cli = match parse_args(env::args().skip(1).collect()) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_USAGE);
         }
     };
 
-    let todo_dir = ".todo";
+    if let Command::Help { topic } = &cli.command {
+        return match help_text(topic.as_deref()) {
+            Some(v) => {
+                // Ignores a closed pipe like t help | head.
+                let _ = writeln!(std::io::stdout(), "{}", v);
+                ExitCode::SUCCESS
+            }
+            None => {
+                eprintln!(
+                    "Error: No help for {}\nsee t help",
+                    topic.as_deref().unwrap_or_default()
+                );
+                ExitCode::from(EXIT_USAGE)
+            }
+        };
+    }
 
-    if let Err(err) = std::fs::create_dir([user_home_dir, todo_dir].join("/")) {
-        if err.kind() != ErrorKind::AlreadyExists {
-            println!("Error. Todo directory could not be created: {}", err);
-            return;
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(EXIT_FAILURE)
         }
     }
+}
 
-    let todo_file_path = [user_home_dir, todo_dir, "todo.txt"].join("/");
-    let id_file_path = [user_home_dir, todo_dir, "id.txt"].join("/");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let args: Vec<String> = env::args().collect();
+    fn todos(ids: &[usize]) -> Vec<Todo> {
+        ids.iter().map(|id| Todo::new_with_id(*id)).collect()
+    }
 
-    let first_arg: String;
-    if args.len() > 1 {
-        first_arg = args[1].clone();
-    } else {
-        first_arg = String::from("");
-    }
-
-    match &first_arg[..] {
-        "ls" => list_all_todos(args, false, &todo_file_path),
-        "lsa" => list_all_todos(args, true, &todo_file_path),
-        "new" => add_new_todo(args, &id_file_path, &todo_file_path),
-        "set" => set_todo(args, &todo_file_path),
-        "rm" => rm_todo(args, &todo_file_path, &id_file_path),
-        "do" => do_task(args, &todo_file_path),
-        "clean" => clean(&todo_file_path, &id_file_path),
-        _ => print_help(),
+    #[test]
+    fn ranges_only_expand_over_existing_todos() {
+        let config = Config::default();
+        let todos = todos(&[0, 1, 2, 3, 5]);
+
+        let selection = Selection::Ids(vec![2..=3, 0..=3, 5..=5]);
+        assert_eq!(
+            select_ids(&selection, &todos, &config),
+            Ok(vec![2, 3, 0, 1, 5])
+        );
+
+        let selection = Selection::Ids(vec![0..=usize::MAX]);
+        assert_eq!(
+            select_ids(&selection, &todos, &config),
+            Err(String::from("No Todo with ID 4"))
+        );
     }
 }
@@ -0,0 +1,428 @@
+use ansi_term::{Color, Style};
+use chrono::{Local, NaiveDateTime};
+use std::io::{ErrorKind, Stdout, Write};
+use std::time::Duration;
+use tabwriter::TabWriter;
+
+use super::structs::deps::*;
+use super::structs::parse::*;
+use super::structs::table::*;
+use super::structs::tree::*;
+use super::structs::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+}
+
+impl OutputFormat {
+    pub fn from_string(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(format!(
+                "Unknown format {}, use table, json, ndjson, csv or tsv",
+                s
+            )),
+        }
+    }
+}
+
+// The fields of every todo in the machine readable formats, in this order.
+// Durations are seconds, dates ISO 8601 without a time zone.
+const FIELDS: &[&str] = &[
+    "id",
+    "priority",
+    "description",
+    "projects",
+    "categories",
+    "estimated",
+    "actual",
+    "status",
+    "color",
+    "due",
+    "scheduled",
+    "sessions",
+    "parent",
+    "depends",
+    "recurrence",
+    "blocked",
+    "notes",
+];
+
+// Prints the todos of a command, as a table or in one of the machine
+// readable formats. Headings and headers only show up in the table.
+pub struct TodoPrinter {
+    format: OutputFormat,
+    style: TableStyle,
+    tw: TabWriter<Stdout>,
+    // Whether the todos come from several lists, each shown with the name
+    // of its list in front.
+    lists: bool,
+    list: String,
+    todos: Vec<(String, Todo)>,
+    // Taken once, so all rows agree on what is overdue.
+    now: NaiveDateTime,
+}
+
+impl TodoPrinter {
+    pub fn new(format: OutputFormat, style: &TableStyle) -> TodoPrinter {
+        TodoPrinter {
+            format,
+            style: style.clone(),
+            tw: TabWriter::new(std::io::stdout()).padding(2),
+            lists: false,
+            list: String::new(),
+            todos: Vec::new(),
+            now: Local::now().naive_local(),
+        }
+    }
+    // Adds a List column to the table and a list field to the other formats.
+    pub fn with_lists(format: OutputFormat, style: &TableStyle) -> TodoPrinter {
+        TodoPrinter {
+            lists: true,
+            ..TodoPrinter::new(format, style)
+        }
+    }
+    // The list of the todos pushed from now on.
+    pub fn set_list(&mut self, list: &str) {
+        self.list = list.to_string();
+    }
+    pub fn heading(&mut self, heading: &str) {
+        if self.format == OutputFormat::Table {
+            self.tw
+                .write_all(format!("{}\n", heading).as_bytes())
+                .unwrap();
+        }
+    }
+    pub fn header(&mut self) {
+        if self.format == OutputFormat::Table {
+            let header = if self.lists {
+                format!("List\t{}", self.style.header())
+            } else {
+                self.style.header()
+            };
+            self.tw.write_all(header.as_bytes()).unwrap();
+        }
+    }
+    pub fn push(&mut self, todo: &Todo) {
+        self.push_shown(todo, todo);
+    }
+    // The table shows `shown`, a copy prepared for display, the other
+    // formats the todo itself.
+    pub fn push_shown(&mut self, todo: &Todo, shown: &Todo) {
+        if self.format == OutputFormat::Table {
+            let line = if self.lists {
+                format!("{}\t{}\n", self.list, self.style.row(shown, self.now))
+            } else {
+                format!("{}\n", self.style.row(shown, self.now))
+            };
+            self.tw.write_all(line.as_bytes()).unwrap();
+        } else {
+            self.todos.push((self.list.clone(), todo.clone()));
+        }
+    }
+    // A closed pipe (t ls --format json | head) is not an error.
+    pub fn flush(self) -> std::io::Result<()> {
+        match self.write_out() {
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    }
+    fn write_out(mut self) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout();
+        match self.format {
+            OutputFormat::Table => return self.tw.flush(),
+            OutputFormat::Json => {
+                let objects: Vec<String> = self
+                    .todos
+                    .iter()
+                    .map(|(list, todo)| format!("  {}", self.json(list, todo)))
+                    .collect();
+                if objects.is_empty() {
+                    stdout.write_all("[]\n".as_bytes())?;
+                } else {
+                    stdout.write_all(format!("[\n{}\n]\n", objects.join(",\n")).as_bytes())?;
+                }
+            }
+            OutputFormat::Ndjson => {
+                for (list, todo) in self.todos.iter() {
+                    stdout.write_all(format!("{}\n", self.json(list, todo)).as_bytes())?;
+                }
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                let (separator, field): (&str, fn(&str) -> String) =
+                    if self.format == OutputFormat::Csv {
+                        (",", csv_field)
+                    } else {
+                        ("\t", tsv_field)
+                    };
+                let mut names = FIELDS.to_vec();
+                if self.lists {
+                    names.insert(0, "list");
+                }
+                stdout.write_all(format!("{}\n", names.join(separator)).as_bytes())?;
+                for (list, todo) in self.todos.iter() {
+                    let mut values = todo_values(todo);
+                    if self.lists {
+                        values.insert(0, list.clone());
+                    }
+                    let values: Vec<String> = values.iter().map(|v| field(v)).collect();
+                    stdout.write_all(format!("{}\n", values.join(separator)).as_bytes())?;
+                }
+            }
+        }
+        stdout.flush()
+    }
+    // A todo with the name of its list as the first field.
+    fn json(&self, list: &str, todo: &Todo) -> String {
+        let json = todo_to_json(todo);
+        if self.lists {
+            format!("{{\"list\":{},{}", json_string(list), &json[1..])
+        } else {
+            json
+        }
+    }
+}
+
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn tsv_field(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn iso_date_time(d: NaiveDateTime) -> String {
+    d.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+// Due and scheduled dates without a time of day stay plain dates.
+fn iso_date(d: NaiveDateTime) -> String {
+    if is_date_only(d) {
+        d.format("%Y-%m-%d").to_string()
+    } else {
+        iso_date_time(d)
+    }
+}
+
+// Durations that were never set are 0, like in the todo file.
+fn seconds(d: Option<Duration>) -> String {
+    d.unwrap_or_default().as_secs().to_string()
+}
+
+// The fields of a todo as text for csv and tsv, empty when not set.
+fn todo_values(todo: &Todo) -> Vec<String> {
+    vec![
+        todo.get_id().to_string(),
+        todo.get_priority().to_string(),
+        todo.get_description(),
+        todo.get_projects(),
+        todo.get_categories(),
+        seconds(todo.get_time_estimated()),
+        seconds(todo.get_time_actual()),
+        todo.get_status().to_string(),
+        color_to_string(todo.get_color()),
+        todo.get_due().map(iso_date).unwrap_or_default(),
+        todo.get_scheduled().map(iso_date).unwrap_or_default(),
+        sessions_to_string(todo.get_sessions()),
+        option_usize_to_string(todo.get_parent()),
+        usize_list_to_string(todo.get_depends()),
+        todo.get_recurrence()
+            .map(recurrence_to_string)
+            .unwrap_or_default(),
+        todo.is_blocked().to_string(),
+        todo.get_notes(),
+    ]
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_option(v: Option<String>) -> String {
+    v.unwrap_or_else(|| String::from("null"))
+}
+
+pub fn todo_to_json(todo: &Todo) -> String {
+    let sessions: Vec<String> = todo
+        .get_sessions()
+        .iter()
+        .map(|session| {
+            format!(
+                "{{\"start\":{},\"end\":{}}}",
+                json_string(&iso_date_time(session.start)),
+                json_option(session.end.map(|v| json_string(&iso_date_time(v))))
+            )
+        })
+        .collect();
+    let values = [
+        todo.get_id().to_string(),
+        todo.get_priority().to_string(),
+        json_string(&todo.get_description()),
+        json_string(&todo.get_projects()),
+        json_string(&todo.get_categories()),
+        seconds(todo.get_time_estimated()),
+        seconds(todo.get_time_actual()),
+        json_string(&todo.get_status().to_string()),
+        json_string(&color_to_string(todo.get_color())),
+        json_option(todo.get_due().map(|v| json_string(&iso_date(v)))),
+        json_option(todo.get_scheduled().map(|v| json_string(&iso_date(v)))),
+        format!("[{}]", sessions.join(",")),
+        json_option(todo.get_parent().map(|v| v.to_string())),
+        format!("[{}]", usize_list_to_string(todo.get_depends())),
+        json_option(
+            todo.get_recurrence()
+                .map(|v| json_string(&recurrence_to_string(v))),
+        ),
+        todo.is_blocked().to_string(),
+        json_string(&todo.get_notes()),
+    ];
+
+    let pairs: Vec<String> = FIELDS
+        .iter()
+        .zip(values.iter())
+        .map(|(name, value)| format!("\"{}\":{}", name, value))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn id_list(ids: &[usize]) -> String {
+    ids.iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// Everything about one todo for t show: the fields that are set, its
+// relations to other todos, the tracked time and the notes.
+pub fn todo_details(todo: &Todo, todos: &[Todo], style: &TableStyle, now: NaiveDateTime) -> String {
+    let mut fields: Vec<(&str, String)> = vec![
+        ("Status", todo.get_status().to_string()),
+        ("Priority", todo.get_priority().to_string()),
+        ("Projects", todo.get_projects()),
+        ("Categories", todo.get_categories()),
+        (
+            "Due",
+            todo.get_due().map(|v| style.date(v)).unwrap_or_default(),
+        ),
+        (
+            "Scheduled",
+            todo.get_scheduled()
+                .map(|v| style.date(v))
+                .unwrap_or_default(),
+        ),
+        (
+            "Recurrence",
+            todo.get_recurrence()
+                .map(recurrence_to_string)
+                .unwrap_or_default(),
+        ),
+        (
+            "Estimated",
+            duration_to_human_string(todo.get_time_estimated().unwrap_or_default()),
+        ),
+        (
+            "Actual",
+            duration_to_human_string(todo.get_time_actual().unwrap_or_default()),
+        ),
+    ];
+    if todo.get_color() != Color::White {
+        fields.push(("Color", color_to_string(todo.get_color())));
+    }
+    if let Some(parent) = todo.get_parent() {
+        let description = todos
+            .iter()
+            .find(|t| t.get_id() == parent)
+            .map(|t| t.get_description())
+            .unwrap_or_default();
+        fields.push(("Parent", format!("{} {}", parent, description)));
+    }
+    if let Some(rollup) = rollup(todos, todo.get_id()) {
+        let children: Vec<usize> = children_of(todos, todo.get_id())
+            .iter()
+            .map(|t| t.get_id())
+            .collect();
+        fields.push((
+            "Subtasks",
+            format!(
+                "{} ({} of {} done)",
+                id_list(&children),
+                rollup.children_done,
+                rollup.children
+            ),
+        ));
+    }
+    if !todo.get_depends().is_empty() {
+        let open = open_dependencies(todos, todo);
+        let mut depends = id_list(todo.get_depends());
+        if !open.is_empty() {
+            depends.push_str(&format!(" (blocked by {})", id_list(&open)));
+        }
+        fields.push(("Depends on", depends));
+    }
+    let sessions = todo.get_sessions();
+    if !sessions.is_empty() {
+        let total = sessions
+            .iter()
+            .fold(Duration::from_secs(0), |sum, session| {
+                sum + session.duration(now)
+            });
+        let mut tracked = format!(
+            "{} in {} sessions",
+            duration_to_human_string(total),
+            sessions.len()
+        );
+        if let Some(session) = todo.running_session() {
+            tracked.push_str(&format!(", running since {}", style.date(session.start)));
+        }
+        fields.push(("Tracked", tracked));
+    }
+
+    let title = format!("{} {}", todo.get_id(), todo.get_description());
+    let mut out = if style.color {
+        Style::from(todo.get_color())
+            .bold()
+            .paint(title)
+            .to_string()
+    } else {
+        title
+    };
+    out.push_str("\n\n");
+    for (name, value) in fields.iter().filter(|(_, value)| !value.is_empty()) {
+        out.push_str(&format!("{:<12}{}\n", format!("{}:", name), value));
+    }
+    let notes = todo.get_notes();
+    if !notes.is_empty() {
+        out.push('\n');
+        out.push_str(&notes);
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,759 @@
+use chrono::NaiveDateTime;
+use regex::{Regex, RegexBuilder};
+use std::cmp::Ordering;
+use std::fmt;
+use std::time::Duration;
+
+use super::structs::parse::*;
+use super::structs::*;
+
+// Filter expressions shared by ls, report and the other commands that pick
+// todos, e.g. `status:doing prio>3 (proj:web or proj:api) and not cat:bug`.
+// Words without a field keep working like the old search.
+
+#[derive(Debug)]
+pub struct QueryError {
+    msg: String,
+    query: String,
+    start: usize,
+    len: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Query error: {}\n  {}\n  {}{}",
+            self.msg,
+            self.query,
+            " ".repeat(self.start),
+            "^".repeat(self.len.max(1))
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Field {
+    Id,
+    Status,
+    Priority,
+    Description,
+    Projects,
+    Categories,
+    Estimated,
+    Actual,
+    Due,
+    Scheduled,
+    Color,
+    Parent,
+    Notes,
+}
+
+impl Field {
+    fn from_name(s: &str) -> Option<Field> {
+        match &s.to_lowercase()[..] {
+            "id" => Some(Field::Id),
+            "status" | "stat" => Some(Field::Status),
+            "prio" | "priority" => Some(Field::Priority),
+            "desc" | "description" => Some(Field::Description),
+            "proj" | "project" | "projects" => Some(Field::Projects),
+            "cat" | "category" | "categories" => Some(Field::Categories),
+            "est" | "estimated" => Some(Field::Estimated),
+            "act" | "actual" => Some(Field::Actual),
+            "due" => Some(Field::Due),
+            "sched" | "scheduled" => Some(Field::Scheduled),
+            "color" => Some(Field::Color),
+            "parent" => Some(Field::Parent),
+            "notes" | "note" => Some(Field::Notes),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Op {
+    // field:value, contains for text, equal otherwise
+    Has,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Matches,
+}
+
+impl Op {
+    fn is_comparison(self) -> bool {
+        matches!(self, Op::Lt | Op::Le | Op::Gt | Op::Ge)
+    }
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Has | Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Matches => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Value {
+    Text(String),
+    Regex(Regex),
+    Number(Option<i64>),
+    Duration(Duration),
+    Date(Option<NaiveDateTime>),
+    Status(TodoStatus),
+}
+
+#[derive(Debug)]
+enum Expr {
+    All,
+    Search(String),
+    Condition(Field, Op, Value),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, todo: &Todo) -> bool {
+        match self {
+            Expr::All => true,
+            Expr::Search(needle) => todo.filter(needle),
+            Expr::Condition(field, op, value) => condition_matches(todo, *field, *op, value),
+            Expr::Not(e) => !e.matches(todo),
+            Expr::And(a, b) => a.matches(todo) && b.matches(todo),
+            Expr::Or(a, b) => a.matches(todo) || b.matches(todo),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Query {
+    expr: Expr,
+}
+
+impl Query {
+    // Parses a query, estimates and dates are read like in `t set`.
+    pub fn parse(s: &str, work_day: Duration) -> Result<Query, QueryError> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            query: s,
+            tokens,
+            pos: 0,
+            work_day,
+        };
+        if parser.tokens.is_empty() {
+            return Ok(Query { expr: Expr::All });
+        }
+
+        let expr = parser.parse_or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Query { expr }),
+            Some(token) => Err(parser.error(token, "unexpected )")),
+        }
+    }
+
+    pub fn matches(&self, todo: &Todo) -> bool {
+        self.expr.matches(todo)
+    }
+}
+
+// Compares optional values, comparisons never match a missing value.
+fn compare_options<T: Ord>(op: Op, actual: Option<T>, value: Option<T>) -> bool {
+    match (actual, value) {
+        (Some(a), Some(b)) => op.accepts(a.cmp(&b)),
+        _ if op.is_comparison() => false,
+        (a, b) => op.accepts(if a == b {
+            Ordering::Equal
+        } else {
+            Ordering::Less
+        }),
+    }
+}
+
+fn condition_matches(todo: &Todo, field: Field, op: Op, value: &Value) -> bool {
+    let text = match field {
+        Field::Description => Some(todo.get_description()),
+        Field::Projects => Some(todo.get_projects()),
+        Field::Categories => Some(todo.get_categories()),
+        Field::Color => Some(color_to_string(todo.get_color())),
+        Field::Notes => Some(todo.get_notes()),
+        _ => None,
+    };
+
+    match (value, text) {
+        (Value::Regex(re), Some(text)) => re.is_match(&text),
+        (Value::Text(needle), Some(text)) => {
+            let (text, needle) = (text.to_lowercase(), needle.to_lowercase());
+            match op {
+                Op::Has if field != Field::Color => text.contains(&needle),
+                Op::Ne => text != needle,
+                _ => text == needle,
+            }
+        }
+        (Value::Status(status), _) => op.accepts(todo.get_status().cmp(status)),
+        (Value::Number(n), _) => {
+            let actual = match field {
+                Field::Id => Some(todo.get_id() as i64),
+                Field::Priority => Some(todo.get_priority() as i64),
+                _ => todo.get_parent().map(|v| v as i64),
+            };
+            compare_options(op, actual, *n)
+        }
+        (Value::Duration(d), _) => {
+            let actual = match field {
+                Field::Estimated => todo.get_time_estimated(),
+                _ => todo.get_time_actual(),
+            };
+            op.accepts(actual.unwrap_or_default().cmp(d))
+        }
+        (Value::Date(d), _) => {
+            let mut actual = match field {
+                Field::Due => todo.get_due(),
+                _ => todo.get_scheduled(),
+            };
+            // A date without a time of day covers the whole day.
+            if let Some(v) = d {
+                if is_date_only(*v) {
+                    actual = actual.map(|a| a.date().and_time(v.time()));
+                }
+            }
+            compare_options(op, actual, *d)
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TokenKind {
+    Open,
+    Close,
+    Word,
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    // Operators are only looked for before quotes or a regex.
+    plain_len: usize,
+    start: usize,
+    len: usize,
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.kind == TokenKind::Word
+            && self.plain_len == self.text.len()
+            && self.text.eq_ignore_ascii_case(keyword)
+    }
+}
+
+// Splits the query on whitespace and parentheses. Double quotes keep
+// spaces and parentheses in a value, so does a regex after ~ (desc~/a b/).
+fn tokenize(s: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '(' || c == ')' {
+            tokens.push(Token {
+                kind: if c == '(' {
+                    TokenKind::Open
+                } else {
+                    TokenKind::Close
+                },
+                text: c.to_string(),
+                plain_len: 1,
+                start: i,
+                len: 1,
+            });
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let mut text = String::new();
+        let mut plain_len = None;
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_whitespace() || c == '(' || c == ')' {
+                break;
+            }
+
+            let closing = match c {
+                '"' => '"',
+                '/' if text.ends_with('~') && plain_len.is_none() => '/',
+                _ => {
+                    text.push(c);
+                    i += 1;
+                    continue;
+                }
+            };
+            plain_len.get_or_insert(text.len());
+            if closing == '/' {
+                text.push('/');
+            }
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        let what = if closing == '"' { "quote" } else { "regex" };
+                        return Err(QueryError {
+                            msg: format!("unterminated {}", what),
+                            query: s.to_string(),
+                            start,
+                            len: i - start,
+                        });
+                    }
+                    Some('\\') if closing == '/' && chars.get(i + 1) == Some(&'/') => {
+                        text.push('/');
+                        i += 2;
+                    }
+                    Some(v) if *v == closing => {
+                        if closing == '/' {
+                            text.push('/');
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(v) => {
+                        text.push(*v);
+                        i += 1;
+                    }
+                }
+            }
+        }
+
+        tokens.push(Token {
+            kind: TokenKind::Word,
+            plain_len: plain_len.unwrap_or(text.len()),
+            text,
+            start,
+            len: i - start,
+        });
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    work_day: Duration,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, token: &Token, msg: &str) -> QueryError {
+        QueryError {
+            msg: msg.to_string(),
+            query: self.query.to_string(),
+            start: token.start,
+            len: token.len,
+        }
+    }
+
+    fn error_at_end(&self, msg: &str) -> QueryError {
+        QueryError {
+            msg: msg.to_string(),
+            query: self.query.to_string(),
+            start: self.query.chars().count(),
+            len: 1,
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(token) => token.is_keyword(keyword),
+            None => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // Conditions next to each other are joined with and.
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_not()?;
+        loop {
+            match self.tokens.get(self.pos) {
+                None => break,
+                Some(token) if token.kind == TokenKind::Close || token.is_keyword("or") => break,
+                Some(token) if token.is_keyword("and") => self.pos += 1,
+                _ => {}
+            }
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, QueryError> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        let token = match self.tokens.get(self.pos) {
+            Some(v) => v,
+            None => return Err(self.error_at_end("expected a condition")),
+        };
+        self.pos += 1;
+
+        match token.kind {
+            TokenKind::Open => {
+                let open = self.pos - 1;
+                let expr = self.parse_or()?;
+                match self.tokens.get(self.pos) {
+                    Some(v) if v.kind == TokenKind::Close => {
+                        self.pos += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(self.error(&self.tokens[open], "unclosed parenthesis")),
+                }
+            }
+            TokenKind::Close => Err(self.error(token, "expected a condition before )")),
+            TokenKind::Word if token.is_keyword("and") || token.is_keyword("or") => Err(self
+                .error(
+                    token,
+                    &format!("expected a condition before {}", token.text),
+                )),
+            TokenKind::Word => self.parse_condition(token),
+        }
+    }
+
+    // A known field with an operator is a condition, every other word is
+    // searched for like before, so wow! and 10:30 still work.
+    fn parse_condition(&self, token: &Token) -> Result<Expr, QueryError> {
+        let search = || Ok(Expr::Search(token.text.clone()));
+        let plain = &token.text[..token.plain_len];
+        let op_start = match plain.find(|c| ":=!<>~".contains(c)) {
+            Some(v) => v,
+            None => return search(),
+        };
+
+        let name = &token.text[..op_start];
+        let field = match Field::from_name(name) {
+            Some(v) => v,
+            None => return search(),
+        };
+        let rest = &token.text[op_start..];
+        let (op, op_len) = if rest.starts_with("!=") {
+            (Op::Ne, 2)
+        } else if rest.starts_with("<=") {
+            (Op::Le, 2)
+        } else if rest.starts_with(">=") {
+            (Op::Ge, 2)
+        } else {
+            match rest.chars().next() {
+                Some(':') => (Op::Has, 1),
+                Some('=') => (Op::Eq, 1),
+                Some('<') => (Op::Lt, 1),
+                Some('>') => (Op::Gt, 1),
+                Some('~') => (Op::Matches, 1),
+                _ => return search(),
+            }
+        };
+
+        let value = &rest[op_len..];
+        if value.is_empty() && token.plain_len == token.text.len() {
+            return Err(self.error(token, &format!("missing value after {}", name)));
+        }
+
+        let value = self.parse_value(token, field, op, value)?;
+        Ok(Expr::Condition(field, op, value))
+    }
+
+    fn parse_value(
+        &self,
+        token: &Token,
+        field: Field,
+        op: Op,
+        value: &str,
+    ) -> Result<Value, QueryError> {
+        let error = |msg: &str| self.error(token, msg);
+        let text_field = matches!(
+            field,
+            Field::Description | Field::Projects | Field::Categories | Field::Color | Field::Notes
+        );
+
+        if op == Op::Matches {
+            if !text_field {
+                return Err(error("~ only works on desc, proj, cat, color and notes"));
+            }
+            let pattern = match value.strip_prefix('/').and_then(|v| v.strip_suffix('/')) {
+                Some(v) => v,
+                None => value,
+            };
+            // ^ and $ match at every line of the notes.
+            return match RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .multi_line(true)
+                .build()
+            {
+                Ok(v) => Ok(Value::Regex(v)),
+                Err(err) => Err(error(&format!("invalid regex: {}", err))),
+            };
+        }
+        if text_field || field == Field::Status {
+            if op.is_comparison() {
+                return Err(error(
+                    "<, <=, > and >= only work on numbers, durations and dates",
+                ));
+            }
+            if field == Field::Status {
+                return match find_status(value) {
+                    Some(v) => Ok(Value::Status(v)),
+                    None => Err(error(&format!("unknown status {}", value))),
+                };
+            }
+            return Ok(Value::Text(value.to_string()));
+        }
+
+        match field {
+            Field::Estimated | Field::Actual => match parse_duration_input(value, self.work_day) {
+                Ok(v) => Ok(Value::Duration(v.unwrap_or_default())),
+                Err(err) => Err(error(&err.to_string())),
+            },
+            Field::Due | Field::Scheduled => match parse_date_input(value) {
+                Ok(None) if op.is_comparison() => Err(error("cannot compare with none")),
+                Ok(v) => Ok(Value::Date(v)),
+                Err(err) => Err(error(&err.to_string())),
+            },
+            _ if field == Field::Parent && value.eq_ignore_ascii_case("none") => {
+                if op.is_comparison() {
+                    return Err(error("cannot compare with none"));
+                }
+                Ok(Value::Number(None))
+            }
+            _ => match value.parse::<i64>() {
+                Ok(v) => Ok(Value::Number(Some(v))),
+                Err(_) => Err(error(&format!("{} is not a number", value))),
+            },
+        }
+    }
+}
+
+// Status names are matched without case, so status:doing works.
+fn find_status(s: &str) -> Option<TodoStatus> {
+    [
+        TodoStatus::New,
+        TodoStatus::Backlog,
+        TodoStatus::Next,
+        TodoStatus::Planned,
+        TodoStatus::Doing,
+        TodoStatus::Review,
+        TodoStatus::Done,
+    ]
+    .iter()
+    .copied()
+    .find(|status| status.to_string().eq_ignore_ascii_case(s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    const WORK_DAY: Duration = Duration::from_secs(8 * 3600);
+
+    fn todos() -> Vec<Todo> {
+        let mut login = Todo::new_with_id(0);
+        login.set_description("Fix login page");
+        login.set_projects("web");
+        login.set_categories("bug");
+        login.set_priority(4);
+        login.set_status(TodoStatus::Doing).unwrap();
+        login.set_time_estimated(Some(Duration::from_secs(3 * 3600)));
+        login.set_due(
+            NaiveDate::from_ymd_opt(2024, 5, 17)
+                .unwrap()
+                .and_hms_opt(0, 0, 0),
+        );
+
+        let mut api = Todo::new_with_id(1);
+        api.set_description("Rate limits");
+        api.set_projects("api");
+        api.set_priority(2);
+        api.set_time_estimated(Some(Duration::from_secs(30 * 60)));
+        api.set_parent(Some(0));
+
+        let mut docs = Todo::new_with_id(2);
+        docs.set_description("Write docs (draft)");
+        docs.set_projects("web docs");
+        docs.set_categories("bug");
+        docs.set_status(TodoStatus::Review).unwrap();
+        docs.set_notes("See the login page\nand the API");
+        vec![login, api, docs]
+    }
+
+    // Ids of the todos the query matches.
+    fn ids(query: &str) -> Vec<usize> {
+        let query = Query::parse(query, WORK_DAY).unwrap();
+        todos()
+            .iter()
+            .filter(|todo| query.matches(todo))
+            .map(|todo| todo.get_id())
+            .collect()
+    }
+
+    fn error(query: &str) -> String {
+        Query::parse(query, WORK_DAY).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(ids(""), vec![0, 1, 2]);
+        assert_eq!(ids("   "), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn words_search_like_before() {
+        assert_eq!(ids("login"), vec![0, 2]);
+        assert_eq!(ids("WEB"), vec![0, 2]);
+        assert_eq!(ids("api"), vec![1, 2]);
+    }
+
+    #[test]
+    fn words_that_are_no_condition_are_searched() {
+        let mut todos = todos();
+        todos[0].set_description("Call at 10:30, wow!");
+        todos[1].set_description("Check a<b and b>c");
+        todos[2].set_description("Done -> ship");
+        let matching = |query: &str| -> Vec<usize> {
+            let query = Query::parse(query, WORK_DAY).unwrap();
+            todos
+                .iter()
+                .filter(|todo| query.matches(todo))
+                .map(|todo| todo.get_id())
+                .collect()
+        };
+
+        assert_eq!(matching("wow!"), vec![0]);
+        assert_eq!(matching("10:30"), vec![0]);
+        assert_eq!(matching("a<b"), vec![1]);
+        assert_eq!(matching("b>c"), vec![1]);
+        assert_eq!(matching("->"), vec![2]);
+        assert_eq!(matching("foo:bar"), Vec::<usize>::new());
+        // A known field with something else than an operator is a word too.
+        assert_eq!(matching("prio!3"), Vec::<usize>::new());
+        assert_eq!(matching("wow! or ->"), vec![0, 2]);
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(ids("status:doing"), vec![0]);
+        assert_eq!(ids("proj:web"), vec![0, 2]);
+        assert_eq!(ids("color:white"), vec![0, 1, 2]);
+        assert_eq!(ids("parent:0"), vec![1]);
+        assert_eq!(ids("parent:none"), vec![0, 2]);
+        assert_eq!(ids("notes:api"), vec![2]);
+        assert_eq!(ids("id!=1"), vec![0, 2]);
+    }
+
+    #[test]
+    fn and_or_not() {
+        assert_eq!(ids("proj:web cat:bug"), vec![0, 2]);
+        assert_eq!(ids("proj:web and status:review"), vec![2]);
+        assert_eq!(ids("proj:api or status:review"), vec![1, 2]);
+        assert_eq!(ids("not cat:bug"), vec![1]);
+        assert_eq!(ids("not not cat:bug"), vec![0, 2]);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // proj:api or (status:review and prio>3)
+        assert_eq!(ids("proj:api or status:review prio>3"), vec![1]);
+        assert_eq!(ids("(proj:api or status:review) prio<3"), vec![1, 2]);
+        assert_eq!(ids("not proj:api or prio>3"), vec![0, 2]);
+        assert_eq!(ids("not (proj:api or prio>3)"), vec![2]);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(ids("desc:\"login page\""), vec![0]);
+        assert_eq!(ids("\"(draft)\""), vec![2]);
+        assert_eq!(ids("desc~/^fix (login|signup)/"), vec![0]);
+        assert_eq!(ids("notes~/^and the/"), vec![2]);
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(ids("prio>=2"), vec![0, 1]);
+        assert_eq!(ids("prio<2"), vec![2]);
+        assert_eq!(ids("est>2h"), vec![0]);
+        assert_eq!(ids("est<=30m"), vec![1, 2]);
+        assert_eq!(ids("due<2024-05-18"), vec![0]);
+        assert_eq!(ids("due:2024-05-17"), vec![0]);
+        assert_eq!(ids("due>2024-05-17"), Vec::<usize>::new());
+        assert_eq!(ids("due:none"), vec![1, 2]);
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(
+            error("AND"),
+            "Query error: expected a condition before AND\n  AND\n  ^^^"
+        );
+        assert_eq!(
+            error("status>=doing"),
+            "Query error: <, <=, > and >= only work on numbers, durations and dates\n  \
+             status>=doing\n  ^^^^^^^^^^^^^"
+        );
+        assert_eq!(
+            error("proj:web (status:new"),
+            "Query error: unclosed parenthesis\n  proj:web (status:new\n           ^"
+        );
+        assert_eq!(
+            error("proj:web)"),
+            "Query error: unexpected )\n  proj:web)\n          ^"
+        );
+        assert_eq!(
+            error("proj:web or"),
+            "Query error: expected a condition\n  proj:web or\n             ^"
+        );
+        assert_eq!(
+            error("desc:\"open"),
+            "Query error: unterminated quote\n  desc:\"open\n  ^^^^^^^^^^"
+        );
+        assert_eq!(
+            error("prio>high"),
+            "Query error: high is not a number\n  prio>high\n  ^^^^^^^^^"
+        );
+        assert_eq!(
+            error("proj>web"),
+            "Query error: <, <=, > and >= only work on numbers, durations and dates\n  \
+             proj>web\n  ^^^^^^^^"
+        );
+        assert_eq!(
+            error("status:later"),
+            "Query error: unknown status later\n  status:later\n  ^^^^^^^^^^^^"
+        );
+        assert_eq!(
+            error("prio~/1/"),
+            "Query error: ~ only works on desc, proj, cat, color and notes\n  prio~/1/\n  ^^^^^^^^"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use chrono::{Duration as ChronoDuration, NaiveDate, NaiveDateTime, NaiveTime};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+use tabwriter::TabWriter;
+
+use super::output::csv_field;
+use super::structs::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportGrouping {
+    Project,
+    Category,
+    Day,
+}
+
+impl ReportGrouping {
+    pub fn from_string(s: &str) -> Result<ReportGrouping, String> {
+        match s {
+            "project" | "proj" => Ok(ReportGrouping::Project),
+            "category" | "cat" => Ok(ReportGrouping::Category),
+            "day" => Ok(ReportGrouping::Day),
+            _ => Err(format!(
+                "Unknown grouping {}, use project, category or day",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ReportRow {
+    pub group: String,
+    pub todos: usize,
+    pub estimated: Duration,
+    pub actual: Duration,
+}
+
+impl ReportRow {
+    // Actual time divided by the estimate, like the Difference column.
+    pub fn ratio(&self) -> Option<f64> {
+        if self.estimated.as_secs() == 0 || self.actual.as_secs() == 0 {
+            return None;
+        }
+        Some(self.actual.as_secs_f64() / self.estimated.as_secs_f64())
+    }
+}
+
+pub struct TimeReport {
+    pub rows: Vec<ReportRow>,
+    pub total: ReportRow,
+}
+
+// Start inclusive, end exclusive. Without a range the whole actual time of a
+// todo counts, including time set by hand. With a range only tracked
+// sessions can be attributed.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ReportRange {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+impl ReportRange {
+    fn is_set(&self) -> bool {
+        self.from.is_some() || self.to.is_some()
+    }
+
+    fn clip(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let start = match self.from {
+            Some(v) => start.max(v),
+            None => start,
+        };
+        let end = match self.to {
+            Some(v) => end.min(v),
+            None => end,
+        };
+        if start < end {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+}
+
+fn to_duration(d: ChronoDuration) -> Duration {
+    d.to_std().unwrap_or_default()
+}
+
+// Tracked time per day, sessions running over midnight are split.
+fn session_time_by_day(
+    todo: &Todo,
+    range: &ReportRange,
+    now: NaiveDateTime,
+) -> BTreeMap<NaiveDate, Duration> {
+    let mut days = BTreeMap::new();
+    for session in todo.get_sessions() {
+        let end = session.end.unwrap_or(now);
+        let (mut start, end) = match range.clip(session.start, end) {
+            Some(v) => v,
+            None => continue,
+        };
+        while start < end {
+            let next_day = (start.date() + ChronoDuration::days(1)).and_time(NaiveTime::MIN);
+            let part_end = end.min(next_day);
+            *days.entry(start.date()).or_insert_with(Duration::default) +=
+                to_duration(part_end - start);
+            start = part_end;
+        }
+    }
+    days
+}
+
+fn tracked_time(todo: &Todo, range: &ReportRange, now: NaiveDateTime) -> Duration {
+    if !range.is_set() {
+        let running = match todo.running_session() {
+            Some(v) => v.duration(now),
+            None => Duration::default(),
+        };
+        return todo.get_time_actual().unwrap_or_default() + running;
+    }
+    session_time_by_day(todo, range, now).values().sum()
+}
+
+// Splits a projects or categories field into single names.
+pub fn split_names(s: &str) -> Vec<String> {
+    let names: Vec<String> = s
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect();
+    if names.is_empty() {
+        vec![String::from("(none)")]
+    } else {
+        names
+    }
+}
+
+// A todo with several projects or categories counts fully for each of them,
+// the total counts it once.
+pub fn build_time_report(
+    todos: &[Todo],
+    grouping: ReportGrouping,
+    range: &ReportRange,
+    now: NaiveDateTime,
+) -> TimeReport {
+    let mut groups: BTreeMap<String, ReportRow> = BTreeMap::new();
+    let mut total = ReportRow {
+        group: String::from("Total"),
+        ..ReportRow::default()
+    };
+
+    for todo in todos.iter() {
+        match grouping {
+            ReportGrouping::Project | ReportGrouping::Category => {
+                let actual = tracked_time(todo, range, now);
+                if range.is_set() && actual.as_secs() == 0 {
+                    continue;
+                }
+                let estimated = todo.get_time_estimated().unwrap_or_default();
+                total.todos += 1;
+                total.estimated += estimated;
+                total.actual += actual;
+
+                let names = if grouping == ReportGrouping::Project {
+                    split_names(&todo.get_projects())
+                } else {
+                    split_names(&todo.get_categories())
+                };
+                for name in names {
+                    let row = groups.entry(name.clone()).or_insert_with(|| ReportRow {
+                        group: name,
+                        ..ReportRow::default()
+                    });
+                    row.todos += 1;
+                    row.estimated += estimated;
+                    row.actual += actual;
+                }
+            }
+            // Estimates are not spread over days.
+            ReportGrouping::Day => {
+                let days = session_time_by_day(todo, range, now);
+                if days.is_empty() {
+                    continue;
+                }
+                total.todos += 1;
+                for (day, worked) in days {
+                    total.actual += worked;
+                    let name = day.format("%Y-%m-%d").to_string();
+                    let row = groups.entry(name.clone()).or_insert_with(|| ReportRow {
+                        group: name,
+                        ..ReportRow::default()
+                    });
+                    row.todos += 1;
+                    row.actual += worked;
+                }
+            }
+        }
+    }
+
+    TimeReport {
+        rows: groups.into_values().collect(),
+        total,
+    }
+}
+
+pub fn hours(d: Duration) -> String {
+    format!("{:.2}", d.as_secs_f64() / 3600.0)
+}
+
+pub fn ratio_to_string(ratio: Option<f64>) -> String {
+    match ratio {
+        Some(v) => format!("{:.2}", v),
+        None => String::from(""),
+    }
+}
+
+fn report_table_row(row: &ReportRow) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\n",
+        row.group,
+        row.todos,
+        duration_to_human_string(row.estimated),
+        duration_to_human_string(row.actual),
+        hours(row.actual),
+        ratio_to_string(row.ratio()),
+    )
+}
+
+pub fn write_report_table(report: &TimeReport, out: &mut dyn Write) -> std::io::Result<()> {
+    let mut tw = TabWriter::new(out).padding(2);
+    tw.write_all("Group\tTodos\tEstimated\tActual\tHours\tRatio\n".as_bytes())?;
+    for row in report.rows.iter() {
+        tw.write_all(report_table_row(row).as_bytes())?;
+    }
+    tw.write_all(report_table_row(&report.total).as_bytes())?;
+    tw.flush()
+}
+
+pub fn write_report_csv(report: &TimeReport, out: &mut dyn Write) -> std::io::Result<()> {
+    writeln!(out, "group,todos,estimated_hours,actual_hours,ratio")?;
+    for row in report.rows.iter().chain(std::iter::once(&report.total)) {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            csv_field(&row.group),
+            row.todos,
+            hours(row.estimated),
+            hours(row.actual),
+            ratio_to_string(row.ratio()),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use chrono::{Local, NaiveDateTime};
+use std::cmp::Ordering;
+
+use super::structs::parse::*;
+use super::structs::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SortField {
+    // The built-in order of Ord for Todo.
+    Default,
+    Id,
+    Status,
+    Priority,
+    Description,
+    Projects,
+    Categories,
+    Estimated,
+    Actual,
+    Due,
+    Scheduled,
+    Color,
+    Parent,
+}
+
+impl SortField {
+    fn from_name(s: &str) -> Option<SortField> {
+        match s {
+            "default" => Some(SortField::Default),
+            "id" => Some(SortField::Id),
+            "status" | "stat" => Some(SortField::Status),
+            "prio" | "priority" => Some(SortField::Priority),
+            "desc" | "description" => Some(SortField::Description),
+            "proj" | "project" | "projects" => Some(SortField::Projects),
+            "cat" | "category" | "categories" => Some(SortField::Categories),
+            "est" | "estimated" => Some(SortField::Estimated),
+            "act" | "actual" => Some(SortField::Actual),
+            "due" => Some(SortField::Due),
+            "sched" | "scheduled" => Some(SortField::Scheduled),
+            "color" => Some(SortField::Color),
+            "parent" => Some(SortField::Parent),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct SortKey {
+    field: SortField,
+    descending: bool,
+}
+
+// A list of sort keys like prio,-due,proj. Every key breaks the ties of the
+// ones before it, a leading - reverses it. Without keys the built-in order
+// is used.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SortOrder {
+    keys: Vec<SortKey>,
+}
+
+impl SortOrder {
+    pub fn parse(s: &str) -> Result<SortOrder, String> {
+        let mut keys = Vec::new();
+        for key in s.split(',').map(|v| v.trim().to_lowercase()) {
+            let (name, descending) = match key.strip_prefix('-') {
+                Some(v) => (v.to_string(), true),
+                None => (key.trim_start_matches('+').to_string(), false),
+            };
+            let field = match SortField::from_name(&name) {
+                Some(v) => v,
+                None => {
+                    return Err(format!(
+                        "Unknown sort key {}, use id, status, prio, desc, proj, cat, est, act, \
+                         due, sched, color, parent or default",
+                        key
+                    ))
+                }
+            };
+            keys.push(SortKey { field, descending });
+        }
+        Ok(SortOrder { keys })
+    }
+
+    pub fn sort(&self, todos: &mut [Todo]) {
+        self.sort_at(todos, Local::now().naive_local());
+    }
+
+    // Sorts with one `now` for every comparison, the order has to stay the
+    // same during the whole sort.
+    pub fn sort_at(&self, todos: &mut [Todo], now: NaiveDateTime) {
+        if self.keys.is_empty() {
+            todos.sort_by(|a, b| a.default_cmp(b, now));
+            return;
+        }
+        todos.sort_by(|a, b| {
+            for key in self.keys.iter() {
+                let ordering = compare_field(a, b, key, now);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            a.get_id().cmp(&b.get_id())
+        });
+    }
+}
+
+// The order of the workflow, not the one of the enum.
+fn status_rank(status: TodoStatus) -> usize {
+    match status {
+        TodoStatus::New => 0,
+        TodoStatus::Backlog => 1,
+        TodoStatus::Next => 2,
+        TodoStatus::Planned => 3,
+        TodoStatus::Doing => 4,
+        TodoStatus::Review => 5,
+        TodoStatus::Done => 6,
+        TodoStatus::Deleted => 7,
+    }
+}
+
+// Todos without the value come last in both directions.
+fn compare_options<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+// Text is compared without case, empty text counts as missing.
+fn compare_text(a: String, b: String, descending: bool) -> Ordering {
+    let value = |s: String| {
+        if s.is_empty() {
+            None
+        } else {
+            Some(s.to_lowercase())
+        }
+    };
+    compare_options(value(a), value(b), descending)
+}
+
+fn compare_field(a: &Todo, b: &Todo, key: &SortKey, now: NaiveDateTime) -> Ordering {
+    let descending = key.descending;
+    let ordering = match key.field {
+        SortField::Default => a.default_cmp(b, now),
+        SortField::Id => a.get_id().cmp(&b.get_id()),
+        SortField::Status => status_rank(a.get_status()).cmp(&status_rank(b.get_status())),
+        SortField::Priority => a.get_priority().cmp(&b.get_priority()),
+        SortField::Color => color_to_string(a.get_color()).cmp(&color_to_string(b.get_color())),
+        SortField::Description => {
+            return compare_text(a.get_description(), b.get_description(), descending)
+        }
+        SortField::Projects => return compare_text(a.get_projects(), b.get_projects(), descending),
+        SortField::Categories => {
+            return compare_text(a.get_categories(), b.get_categories(), descending)
+        }
+        SortField::Estimated => {
+            return compare_options(a.get_time_estimated(), b.get_time_estimated(), descending)
+        }
+        SortField::Actual => {
+            return compare_options(a.get_time_actual(), b.get_time_actual(), descending)
+        }
+        SortField::Due => return compare_options(a.get_due(), b.get_due(), descending),
+        SortField::Scheduled => {
+            return compare_options(a.get_scheduled(), b.get_scheduled(), descending)
+        }
+        SortField::Parent => return compare_options(a.get_parent(), b.get_parent(), descending),
+    };
+
+    if descending {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn todo(id: usize, priority: isize, due: Option<&str>) -> Todo {
+        let mut todo = Todo::new_with_id(id);
+        todo.set_priority(priority);
+        todo.set_due(due.map(at));
+        todo
+    }
+
+    fn ids(todos: &[Todo]) -> Vec<usize> {
+        todos.iter().map(|todo| todo.get_id()).collect()
+    }
+
+    #[test]
+    fn default_order_uses_one_now() {
+        let mut todos = vec![
+            todo(0, 5, None),
+            todo(1, 0, Some("2024-05-15 23:59")),
+            todo(2, 0, Some("2024-05-14 12:00")),
+            todo(3, 1, Some("2024-05-20 00:00")),
+        ];
+        let mut done = todo(4, 9, Some("2024-05-01 00:00"));
+        done.set_status(TodoStatus::Done).unwrap();
+        todos.push(done);
+
+        let order = SortOrder::default();
+        order.sort_at(&mut todos, at("2024-05-15 10:00"));
+        assert_eq!(ids(&todos), vec![2, 1, 0, 3, 4]);
+
+        // After midnight 1 is overdue as well, on the 20th 3 is due today.
+        order.sort_at(&mut todos, at("2024-05-16 00:01"));
+        assert_eq!(ids(&todos), vec![2, 1, 0, 3, 4]);
+        order.sort_at(&mut todos, at("2024-05-20 08:00"));
+        assert_eq!(ids(&todos), vec![2, 1, 3, 0, 4]);
+    }
+}
@@ -0,0 +1,46 @@
+use super::*;
+
+// Ids of the dependencies that still have to be done. Dependencies on todos
+// that no longer exist do not block.
+pub fn open_dependencies(todos: &[Todo], todo: &Todo) -> Vec<usize> {
+    todo.get_depends()
+        .iter()
+        .copied()
+        .filter(|id| {
+            todos
+                .iter()
+                .any(|other| other.get_id() == *id && !other.done())
+        })
+        .collect()
+}
+
+// Updates the computed blocked state of every todo, call it before sorting.
+pub fn refresh_blocked(todos: &mut [Todo]) {
+    let blocked: Vec<bool> = todos
+        .iter()
+        .map(|todo| !open_dependencies(todos, todo).is_empty())
+        .collect();
+    for (todo, blocked) in todos.iter_mut().zip(blocked) {
+        todo.set_blocked(blocked);
+    }
+}
+
+// Whether `id` depending on `on` closes a loop, i.e. `on` already depends on
+// `id` directly or through other todos.
+pub fn would_create_dependency_cycle(todos: &[Todo], id: usize, on: usize) -> bool {
+    let mut seen = Vec::new();
+    let mut queue = vec![on];
+    while let Some(current) = queue.pop() {
+        if current == id {
+            return true;
+        }
+        if seen.contains(&current) {
+            continue;
+        }
+        seen.push(current);
+        if let Some(todo) = todos.iter().find(|todo| todo.get_id() == current) {
+            queue.extend(todo.get_depends().iter().copied());
+        }
+    }
+    false
+}
@@ -0,0 +1,295 @@
+use super::deps::*;
+use super::tree::*;
+use super::*;
+
+// The keys of the t edit file, in the order they are written. They are the
+// attribute names of t set, with dep for the dependencies and notes, whose
+// lines follow indented.
+const EDIT_KEYS: &[&str] = &[
+    "id", "desc", "stat", "prio", "proj", "cat", "est", "act", "due", "sched", "recur", "color",
+    "parent", "dep", "notes",
+];
+
+const EDIT_HEADER: &str = "\
+# Change the values below, save and quit the editor to apply them.
+# Empty values clear a field, lines starting with # are ignored and
+# todos removed from the file stay as they are. Values are written as
+# for t set, see t help dates, durations and recurrence. The lines of
+# the notes are indented below notes:.
+";
+
+fn option_duration_to_string(d: Option<Duration>) -> String {
+    match d {
+        Some(v) => duration_to_human_string(v),
+        None => String::new(),
+    }
+}
+
+// The todos as blocks of key: value lines, starting with their id.
+pub fn todos_to_edit_text(todos: &[Todo]) -> String {
+    let mut text = String::from(EDIT_HEADER);
+    for todo in todos.iter() {
+        let values = [
+            todo.get_id().to_string(),
+            todo.get_description(),
+            todo.get_status().to_string(),
+            todo.get_priority().to_string(),
+            todo.get_projects(),
+            todo.get_categories(),
+            option_duration_to_string(todo.get_time_estimated()),
+            option_duration_to_string(todo.get_time_actual()),
+            option_date_to_string(todo.get_due()),
+            option_date_to_string(todo.get_scheduled()),
+            option_recurrence_to_string(&todo.recurrence),
+            color_to_string(todo.get_color()),
+            option_usize_to_string(todo.get_parent()),
+            usize_list_to_string(todo.get_depends()),
+        ];
+        text.push('\n');
+        for (key, value) in EDIT_KEYS.iter().zip(values.iter()) {
+            if value.is_empty() {
+                text.push_str(&format!("{}:\n", key));
+            } else {
+                text.push_str(&format!("{}: {}\n", key, value));
+            }
+        }
+        text.push_str("notes:\n");
+        for line in todo.get_notes().lines() {
+            if line.trim().is_empty() {
+                text.push('\n');
+            } else {
+                text.push_str(&format!("  {}\n", line));
+            }
+        }
+    }
+    text
+}
+
+// A line of the notes without the indentation of the edit file.
+fn notes_line(line: &str) -> &str {
+    let line = line.trim_end();
+    match line.strip_prefix("  ").or_else(|| line.strip_prefix('\t')) {
+        Some(v) => v,
+        None => line.trim_start(),
+    }
+}
+
+fn status_from_name(s: &str) -> Option<TodoStatus> {
+    [
+        TodoStatus::New,
+        TodoStatus::Backlog,
+        TodoStatus::Next,
+        TodoStatus::Planned,
+        TodoStatus::Doing,
+        TodoStatus::Review,
+        TodoStatus::Done,
+    ]
+    .iter()
+    .find(|status| status.to_string().eq_ignore_ascii_case(s))
+    .copied()
+}
+
+// Sets one field of the todo. Parents and dependencies are checked against
+// `todos`, the whole list with the fields read so far already set, so later
+// blocks still have the values they had before the edit.
+fn apply_field(
+    todo: &mut Todo,
+    key: &str,
+    value: &str,
+    todos: &[Todo],
+    work_day: Duration,
+) -> Result<(), String> {
+    match key {
+        "desc" => todo.set_description(value),
+        "stat" => match status_from_name(value) {
+            Some(v) => todo.status = v,
+            None => {
+                return Err(format!(
+                "Unknown status {}, expected New, Backlog, Next, Planned, Doing, Review or Done",
+                value
+            ))
+            }
+        },
+        "prio" if value.is_empty() => todo.set_priority(0),
+        "prio" => todo.priority = parse_isize(value).map_err(|err| err.msg)?,
+        "proj" => todo.set_projects(value),
+        "cat" => todo.set_categories(value),
+        "est" if value.is_empty() => todo.set_time_estimated(None),
+        "est" => {
+            todo.time_estimated = parse_duration_input(value, work_day).map_err(|err| err.msg)?
+        }
+        "act" if value.is_empty() => todo.set_time_actual(None),
+        "act" => todo.time_actual = parse_duration_input(value, work_day).map_err(|err| err.msg)?,
+        "due" => todo.due = parse_date_input(value).map_err(|err| err.msg)?,
+        "sched" => todo.scheduled = parse_date_input(value).map_err(|err| err.msg)?,
+        "recur" => todo.recurrence = parse_recurrence(value).map_err(|err| err.msg)?,
+        "color" if value.is_empty() => todo.set_color(Color::White),
+        "color" => match parse_color(value) {
+            Some(v) => todo.set_color(v),
+            None => return Err(format!("Unknown color {}", value)),
+        },
+        "parent" if value.is_empty() || value == "none" => todo.set_parent(None),
+        "parent" => {
+            let parent = parse_usize(value).map_err(|err| err.msg)?;
+            if !todos.iter().any(|t| t.get_id() == parent) {
+                return Err(format!("No Todo with ID {}", parent));
+            }
+            if would_create_cycle(todos, todo.get_id(), parent) {
+                return Err(format!("{} is a subtask of {}", parent, todo.get_id()));
+            }
+            todo.set_parent(Some(parent));
+        }
+        "dep" => {
+            let mut depends = Vec::new();
+            for on in parse_usize_list(value).map_err(|err| err.msg)? {
+                if on == todo.get_id() {
+                    return Err(format!("{} cannot depend on itself", on));
+                }
+                if !todos.iter().any(|t| t.get_id() == on) {
+                    return Err(format!("No Todo with ID {}", on));
+                }
+                if would_create_dependency_cycle(todos, todo.get_id(), on) {
+                    return Err(format!(
+                        "{} already depends on {}, this would create a cycle",
+                        on,
+                        todo.get_id()
+                    ));
+                }
+                if !depends.contains(&on) {
+                    depends.push(on);
+                }
+            }
+            todo.set_depends(depends);
+        }
+        _ => {
+            return Err(format!(
+                "Unknown key {}, expected one of {}",
+                key,
+                EDIT_KEYS.join(", ")
+            ))
+        }
+    }
+    Ok(())
+}
+
+enum Block {
+    None,
+    Todo(usize),
+    // A block whose id line has an error, its fields are skipped.
+    Invalid,
+}
+
+// Reads the text of todos_to_edit_text back. Returns the changed todos in
+// the order of the file, or every error with the line it is on.
+pub fn parse_edit_text(
+    text: &str,
+    todos: &[Todo],
+    work_day: Duration,
+) -> Result<Vec<Todo>, Vec<ParseTodoError>> {
+    let mut todos = todos.to_vec();
+    let mut ids: Vec<usize> = Vec::new();
+    let mut errors = Vec::new();
+    let mut block = Block::None;
+    let mut keys: Vec<String> = Vec::new();
+    // The todo and lines of the notes being read, blank lines only count
+    // when more indented lines follow.
+    let mut notes: Option<(usize, Vec<String>)> = None;
+    let mut blank_lines = 0;
+
+    for (i, line) in text.lines().enumerate() {
+        if let Some((_, lines)) = notes.as_mut() {
+            if line.trim().is_empty() {
+                blank_lines += 1;
+                continue;
+            }
+            if line.starts_with([' ', '\t']) {
+                lines.extend(std::iter::repeat_n(String::new(), blank_lines));
+                lines.push(notes_line(line).to_string());
+                blank_lines = 0;
+                continue;
+            }
+        }
+        if let Some((index, lines)) = notes.take() {
+            todos[index].set_notes(&lines.join("\n"));
+            blank_lines = 0;
+        }
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |msg: &str| ParseTodoError::new(&format!("line {}: {}", i + 1, msg));
+
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                errors.push(error(&format!("Expected key: value, got {}", line)));
+                continue;
+            }
+        };
+
+        if key == "id" {
+            keys.clear();
+            block = Block::Invalid;
+            let id = match parse_usize(value) {
+                Ok(v) => v,
+                Err(err) => {
+                    errors.push(error(&err.msg));
+                    continue;
+                }
+            };
+            if ids.contains(&id) {
+                errors.push(error(&format!("Todo {} is in the file twice", id)));
+                continue;
+            }
+            match todos.iter().position(|todo| todo.get_id() == id) {
+                Some(index) => {
+                    ids.push(id);
+                    block = Block::Todo(index);
+                }
+                None => errors.push(error(&format!("No Todo with ID {}", id))),
+            }
+            continue;
+        }
+
+        let index = match block {
+            Block::Todo(v) => v,
+            Block::Invalid => continue,
+            Block::None => {
+                errors.push(error(&format!("Expected id: before {}", key)));
+                continue;
+            }
+        };
+        if keys.iter().any(|k| k == key) {
+            errors.push(error(&format!("{} is given twice", key)));
+            continue;
+        }
+        keys.push(key.to_string());
+
+        if key == "notes" {
+            let first = if value.is_empty() {
+                Vec::new()
+            } else {
+                vec![value.to_string()]
+            };
+            notes = Some((index, first));
+            continue;
+        }
+        let mut todo = todos[index].clone();
+        match apply_field(&mut todo, key, value, &todos, work_day) {
+            Ok(()) => todos[index] = todo,
+            Err(msg) => errors.push(error(&msg)),
+        }
+    }
+    if let Some((index, lines)) = notes {
+        todos[index].set_notes(&lines.join("\n"));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(ids
+        .iter()
+        .filter_map(|id| todos.iter().find(|todo| todo.get_id() == *id).cloned())
+        .collect())
+}
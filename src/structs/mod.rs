@@ -1,9 +1,15 @@
 use ansi_term::Color;
+use chrono::{Local, NaiveDateTime, Weekday};
 use std::cmp::Ordering;
 use std::fmt;
 use std::time::Duration;
 
+pub mod deps;
+pub mod edit;
 pub mod parse;
+pub mod recur;
+pub mod table;
+pub mod tree;
 use parse::*;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -63,7 +69,38 @@ impl TodoIOError {
     }
 }
 
-#[derive(Debug)]
+// One stretch of work on a todo, end is None while the timer runs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WorkSession {
+    pub start: NaiveDateTime,
+    pub end: Option<NaiveDateTime>,
+}
+
+impl WorkSession {
+    pub fn duration(&self, now: NaiveDateTime) -> Duration {
+        let end = self.end.unwrap_or(now);
+        (end - self.start).to_std().unwrap_or_default()
+    }
+}
+
+// How often a todo comes back, see parse::parse_recurrence for the syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecurrenceInterval {
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+    Years(u32),
+    Weekdays(Vec<Weekday>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub interval: RecurrenceInterval,
+    // Counts from the day the todo was done instead of from its due date.
+    pub after_completion: bool,
+}
+
+#[derive(Debug, Clone)]
 pub struct Todo {
     id: usize,
     priority: isize,
@@ -74,6 +111,16 @@ pub struct Todo {
     time_actual: Option<Duration>,
     status: TodoStatus,
     color: Color,
+    due: Option<NaiveDateTime>,
+    scheduled: Option<NaiveDateTime>,
+    sessions: Vec<WorkSession>,
+    parent: Option<usize>,
+    depends: Vec<usize>,
+    recurrence: Option<Recurrence>,
+    // Free text over several lines, escaped in the todo file.
+    notes: String,
+    // Computed from the dependencies by deps::refresh_blocked, not stored.
+    blocked: bool,
 }
 
 impl Default for Todo {
@@ -88,12 +135,19 @@ impl Default for Todo {
             time_actual: None,
             status: TodoStatus::New,
             color: Color::White,
+            due: None,
+            scheduled: None,
+            sessions: Vec::new(),
+            parent: None,
+            depends: Vec::new(),
+            recurrence: None,
+            notes: String::new(),
+            blocked: false,
         }
     }
 }
 
 impl Todo {
-    #[allow(dead_code)]
     pub fn new() -> Todo {
         Todo::default()
     }
@@ -113,7 +167,7 @@ impl Todo {
         };
 
         format!(
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
             self.id,
             self.priority,
             self.description,
@@ -123,6 +177,13 @@ impl Todo {
             time_actual,
             self.status,
             color_to_string(self.color),
+            option_date_to_string(self.due),
+            option_date_to_string(self.scheduled),
+            sessions_to_string(&self.sessions),
+            option_usize_to_string(self.parent),
+            usize_list_to_string(&self.depends),
+            option_recurrence_to_string(&self.recurrence),
+            escape_notes(&self.notes),
         )
     }
     pub fn filter(&self, needle: &str) -> bool {
@@ -137,6 +198,7 @@ impl Todo {
         if self.description.to_uppercase().contains(&needle)
             || self.projects.to_uppercase().contains(&needle)
             || self.categories.to_uppercase().contains(&needle)
+            || self.notes.to_uppercase().contains(&needle)
         {
             return true;
         }
@@ -145,66 +207,52 @@ impl Todo {
     pub fn set_deleted(&mut self) {
         self.status = TodoStatus::Deleted;
     }
-}
-
-impl fmt::Display for Todo {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let time_estimated = self.time_estimated.unwrap_or_default();
-        let time_actual = self.time_actual.unwrap_or_default();
-
-        let mut time_prop: f64 = 0.0;
-        let time_delta = match (time_estimated.as_secs(), time_actual.as_secs()) {
-            (0, _) | (_, 0) => Duration::from_secs(0),
-            (_, _) => {
-                time_prop = time_actual.as_secs() as f64 / time_estimated.as_secs() as f64;
-                if time_estimated < time_actual {
-                    time_actual - time_estimated
-                } else {
-                    time_estimated - time_actual
-                }
-            }
+    pub fn due_state(&self, now: NaiveDateTime) -> DueState {
+        let due = match self.due {
+            Some(v) if !self.done() => v,
+            _ => return DueState::None,
         };
 
-        let time_estimated = duration_to_human_string(time_estimated);
-        let time_actual = duration_to_human_string(time_actual);
-        let time_delta = duration_to_human_string(time_delta);
-
-        let time_prop_str = if time_prop == 0.0 {
-            String::from("")
+        // A due date without a time of day is due until the end of that day.
+        let overdue = if is_date_only(due) {
+            due.date() < now.date()
         } else {
-            let mut x = "/".to_string();
-            x.push_str(&format!("{:.2}", time_prop));
-            x
+            due < now
         };
 
-        let print_color = if self.status == TodoStatus::Done {
-            Color::Fixed(8)
+        if overdue {
+            DueState::Overdue
+        } else if due.date() == now.date() {
+            DueState::Today
         } else {
-            self.color
-        };
+            DueState::Later
+        }
+    }
+}
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DueState {
+    Overdue,
+    Today,
+    Later,
+    None,
+}
+
+// A line of the table with the default columns.
+impl fmt::Display for Todo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
             "{}",
-            print_color.paint(format!(
-                "{}\t{:02}\t{}\t{}\t{}\t{}\t{}\t{}\t{}{}",
-                self.id,
-                self.status,
-                self.priority,
-                self.description,
-                self.projects,
-                self.categories,
-                time_estimated,
-                time_actual,
-                time_delta,
-                time_prop_str,
-            ))
+            table::TableStyle::default().row(self, Local::now().naive_local())
         )
     }
 }
 
-impl Ord for Todo {
-    fn cmp(&self, other: &Self) -> Ordering {
+impl Todo {
+    // The built-in order. The caller takes `now` once for a whole sort, so
+    // no todo turns overdue in the middle of it.
+    pub fn default_cmp(&self, other: &Todo, now: NaiveDateTime) -> Ordering {
         if self.id == other.id {
             return Ordering::Equal;
         }
@@ -217,20 +265,30 @@ impl Ord for Todo {
             return self.projects.cmp(&other.projects);
         }
 
+        if self.blocked != other.blocked {
+            return self.blocked.cmp(&other.blocked);
+        }
+
+        // Overdue todos come first, then the ones due today.
+        let due_states = (self.due_state(now), other.due_state(now));
+        if due_states.0 != due_states.1
+            && (due_states.0 <= DueState::Today || due_states.1 <= DueState::Today)
+        {
+            return due_states.0.cmp(&due_states.1);
+        }
+
         if self.priority == other.priority {
-            other.status.cmp(&self.status)
+            if self.status == other.status {
+                compare_due_dates(self.due, other.due)
+            } else {
+                other.status.cmp(&self.status)
+            }
         } else {
             other.priority.cmp(&self.priority)
         }
     }
 }
 
-impl PartialOrd for Todo {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
 impl Eq for Todo {}
 impl PartialEq for Todo {
     fn eq(&self, other: &Self) -> bool {
@@ -238,7 +296,17 @@ impl PartialEq for Todo {
     }
 }
 
-fn duration_to_human_string(d: Duration) -> String {
+// Earlier due dates first, todos without a due date last.
+fn compare_due_dates(a: Option<NaiveDateTime>, b: Option<NaiveDateTime>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+pub fn duration_to_human_string(d: Duration) -> String {
     let s = d.as_secs();
     let seconds = s % 60;
     let minutes = (s / 60) % 60;
@@ -284,7 +352,7 @@ impl Todo {
     }
 
     #[allow(dead_code)]
-    pub fn get_description(&mut self) -> String {
+    pub fn get_description(&self) -> String {
         self.description.clone()
     }
     pub fn set_description(&mut self, description: &str) {
@@ -317,8 +385,9 @@ impl Todo {
     pub fn set_time_estimated_from_string(
         &mut self,
         time_estimated: &str,
+        work_day: Duration,
     ) -> Result<Option<Duration>, ParseTodoError> {
-        self.time_estimated = parse_duration_result(time_estimated)?;
+        self.time_estimated = parse_duration_input(time_estimated, work_day)?;
         Ok(self.get_time_estimated())
     }
 
@@ -333,9 +402,10 @@ impl Todo {
     pub fn set_time_actual_from_string(
         &mut self,
         time_actual: &str,
+        work_day: Duration,
     ) -> Result<Option<Duration>, ParseTodoError> {
-        self.time_actual = parse_duration_result(time_actual)?;
-        Ok(self.get_time_estimated())
+        self.time_actual = parse_duration_input(time_actual, work_day)?;
+        Ok(self.get_time_actual())
     }
 
     #[allow(dead_code)]
@@ -361,8 +431,137 @@ impl Todo {
         Ok(self.get_status())
     }
 
+    pub fn get_due(&self) -> Option<NaiveDateTime> {
+        self.due
+    }
+    pub fn set_due(&mut self, due: Option<NaiveDateTime>) {
+        self.due = due;
+    }
+    pub fn set_due_from_string(
+        &mut self,
+        due: &str,
+    ) -> Result<Option<NaiveDateTime>, ParseTodoError> {
+        self.due = parse_date_input(due)?;
+        Ok(self.get_due())
+    }
+
+    pub fn get_scheduled(&self) -> Option<NaiveDateTime> {
+        self.scheduled
+    }
+    pub fn set_scheduled(&mut self, scheduled: Option<NaiveDateTime>) {
+        self.scheduled = scheduled;
+    }
+    pub fn set_scheduled_from_string(
+        &mut self,
+        scheduled: &str,
+    ) -> Result<Option<NaiveDateTime>, ParseTodoError> {
+        self.scheduled = parse_date_input(scheduled)?;
+        Ok(self.get_scheduled())
+    }
+
+    pub fn get_parent(&self) -> Option<usize> {
+        self.parent
+    }
+    pub fn set_parent(&mut self, parent: Option<usize>) {
+        self.parent = parent;
+    }
+
+    pub fn get_depends(&self) -> &[usize] {
+        &self.depends
+    }
+    pub fn set_depends(&mut self, depends: Vec<usize>) {
+        self.depends = depends;
+    }
+    pub fn add_dependency(&mut self, id: usize) {
+        if !self.depends.contains(&id) {
+            self.depends.push(id);
+        }
+    }
+    pub fn remove_dependency(&mut self, id: usize) -> bool {
+        let len = self.depends.len();
+        self.depends.retain(|d| *d != id);
+        len != self.depends.len()
+    }
+    pub fn get_recurrence(&self) -> Option<&Recurrence> {
+        self.recurrence.as_ref()
+    }
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) {
+        self.recurrence = recurrence;
+    }
+    pub fn set_recurrence_from_string(
+        &mut self,
+        recurrence: &str,
+    ) -> Result<Option<&Recurrence>, ParseTodoError> {
+        self.recurrence = parse_recurrence(recurrence)?;
+        Ok(self.get_recurrence())
+    }
+
+    pub fn get_notes(&self) -> String {
+        self.notes.clone()
+    }
+    pub fn set_notes(&mut self, notes: &str) {
+        self.notes = notes.trim_end().to_string();
+    }
+    // Appends the text as new lines below the notes there are.
+    pub fn add_note(&mut self, note: &str) {
+        let note = note.trim_end();
+        if note.is_empty() {
+            return;
+        }
+        if !self.notes.is_empty() {
+            self.notes.push('\n');
+        }
+        self.notes.push_str(note);
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        self.blocked
+    }
+    pub fn set_blocked(&mut self, blocked: bool) {
+        self.blocked = blocked;
+    }
+
+    pub fn get_sessions(&self) -> &[WorkSession] {
+        &self.sessions
+    }
+    pub fn set_sessions(&mut self, sessions: Vec<WorkSession>) {
+        self.sessions = sessions;
+    }
+    pub fn running_session(&self) -> Option<&WorkSession> {
+        self.sessions.iter().find(|session| session.end.is_none())
+    }
+    // Starts the timer and moves the todo to Doing.
+    pub fn start_session(&mut self, now: NaiveDateTime) -> Result<(), ParseTodoError> {
+        if let Some(session) = self.running_session() {
+            return Err(ParseTodoError::new(&format!(
+                "Timer already running since {}",
+                date_to_string(session.start)
+            )));
+        }
+        self.sessions.push(WorkSession {
+            start: now,
+            end: None,
+        });
+        if self.status != TodoStatus::Done {
+            self.status = TodoStatus::Doing;
+        }
+        Ok(())
+    }
+    // Stops the running timer and adds the session to time_actual.
+    pub fn stop_session(&mut self, now: NaiveDateTime) -> Option<Duration> {
+        let session = self
+            .sessions
+            .iter_mut()
+            .find(|session| session.end.is_none())?;
+        session.end = Some(now.max(session.start));
+
+        let worked = session.duration(now);
+        self.time_actual = Some(self.time_actual.unwrap_or_default() + worked);
+        Some(worked)
+    }
+
     #[allow(dead_code)]
-    pub fn get_color(&mut self) -> Color {
+    pub fn get_color(&self) -> Color {
         self.color
     }
     #[allow(dead_code)]
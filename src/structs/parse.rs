@@ -1,4 +1,8 @@
 use ansi_term::Color;
+use chrono::{
+    Datelike, Duration as ChronoDuration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime,
+    Weekday,
+};
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -38,23 +42,640 @@ pub fn parse_isize(s: &str) -> Result<isize, ParseTodoError> {
     }
 }
 
+pub fn parse_option_usize(s: &str) -> Result<Option<usize>, ParseTodoError> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(parse_usize(s)?))
+}
+
+pub fn option_usize_to_string(u: Option<usize>) -> String {
+    match u {
+        Some(v) => v.to_string(),
+        None => String::from(""),
+    }
+}
+
+pub fn parse_usize_list(s: &str) -> Result<Vec<usize>, ParseTodoError> {
+    let mut list = Vec::new();
+    for part in s.split(',') {
+        if !part.trim().is_empty() {
+            list.push(parse_usize(part.trim())?);
+        }
+    }
+    Ok(list)
+}
+
+pub fn usize_list_to_string(list: &[usize]) -> String {
+    list.iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
 pub fn parse_string(s: &str) -> String {
     s.to_string().replace("\t", "    ")
 }
 
+// Notes keep their tabs and newlines, escaped like in the tsv output so
+// the todo stays on one line.
+pub fn escape_notes(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+// A backslash before anything else is kept as it is. \s is a space, the
+// todo.txt export writes notes as one word.
+pub fn unescape_notes(s: &str) -> String {
+    let mut notes = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            notes.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => notes.push('\n'),
+            Some('t') => notes.push('\t'),
+            Some('r') => notes.push('\r'),
+            Some('s') => notes.push(' '),
+            Some('\\') => notes.push('\\'),
+            Some(other) => {
+                notes.push('\\');
+                notes.push(other);
+            }
+            None => notes.push('\\'),
+        }
+    }
+    notes
+}
+
 pub fn parse_duration_result(s: &str) -> Result<Option<Duration>, ParseTodoError> {
     let u = parse_u64(s)?;
     Ok(Some(Duration::from_secs(u)))
 }
 
+// Parses durations typed by the user, like 1h30m, 90m, 1.5h or 2d. A day
+// is one working day, a week five of them. Bare integers are seconds, so
+// the output of duration_to_human_string (01h30m) and old values both work.
+pub fn parse_duration_input(
+    s: &str,
+    work_day: Duration,
+) -> Result<Option<Duration>, ParseTodoError> {
+    let s = s.trim();
+    if s.chars().all(|c| c.is_ascii_digit()) && !s.is_empty() {
+        return parse_duration_result(s);
+    }
+
+    let error = |msg: &str| {
+        ParseTodoError::new(&format!(
+            "Error parsing duration {}: {}, expected e.g. 1h30m, 90m, 1.5h or 2d",
+            s, msg
+        ))
+    };
+
+    if s.is_empty() {
+        return Err(error("no duration given"));
+    }
+
+    let mut total: f64 = 0.0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+
+        let unit_secs = match c {
+            's' => 1.0,
+            'm' => 60.0,
+            'h' => 3600.0,
+            'd' => work_day.as_secs_f64(),
+            'w' => 5.0 * work_day.as_secs_f64(),
+            _ => return Err(error(&format!("unknown unit {}", c))),
+        };
+        if number.is_empty() {
+            return Err(error(&format!("missing number before {}", c)));
+        }
+        let value = match number.parse::<f64>() {
+            Ok(v) => v,
+            Err(_) => return Err(error(&format!("{} is not a number", number))),
+        };
+        total += value * unit_secs;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return Err(error(&format!("missing unit after {}", number)));
+    }
+
+    Ok(Some(Duration::from_secs(total.round() as u64)))
+}
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATE_TIME_FORMATS: &[&str] = &["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"];
+
+pub fn is_date_only(d: NaiveDateTime) -> bool {
+    d.time() == NaiveTime::MIN
+}
+
+pub fn date_to_string(d: NaiveDateTime) -> String {
+    if is_date_only(d) {
+        d.format(DATE_FORMAT).to_string()
+    } else {
+        d.format("%Y-%m-%d %H:%M").to_string()
+    }
+}
+
+pub fn option_date_to_string(d: Option<NaiveDateTime>) -> String {
+    match d {
+        Some(v) => date_to_string(v),
+        None => String::from(""),
+    }
+}
+
+// An empty string or "none" clears the date.
+pub fn parse_date_result(s: &str) -> Result<Option<NaiveDateTime>, ParseTodoError> {
+    let s = s.trim();
+    if s.is_empty() || s.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    if let Ok(v) = NaiveDate::parse_from_str(s, DATE_FORMAT) {
+        return Ok(Some(v.and_time(NaiveTime::MIN)));
+    }
+    for format in DATE_TIME_FORMATS.iter() {
+        if let Ok(v) = NaiveDateTime::parse_from_str(s, format) {
+            return Ok(Some(v));
+        }
+    }
+
+    Err(ParseTodoError::new(&format!(
+        "Error parsing {} to a date, expected YYYY-MM-DD or YYYY-MM-DD HH:MM",
+        s
+    )))
+}
+
+// Parses dates typed by the user. Besides the formats of parse_date_result
+// this understands today, tomorrow, yesterday, weekdays (mon, next friday),
+// offsets (+3d, 2w, -1m, 4h), eow, eom, eoy and a time of day (14:30), which
+// can also follow any of the others (fri 9:00).
+pub fn parse_date_input(s: &str) -> Result<Option<NaiveDateTime>, ParseTodoError> {
+    parse_date_input_at(s, Local::now().naive_local())
+}
+
+pub fn parse_date_input_at(
+    s: &str,
+    now: NaiveDateTime,
+) -> Result<Option<NaiveDateTime>, ParseTodoError> {
+    let s = s.trim();
+    if let Ok(v) = parse_date_result(s) {
+        return Ok(v);
+    }
+
+    let lower = s.to_lowercase();
+    let mut tokens: Vec<&str> = lower.split_whitespace().collect();
+
+    let mut time = None;
+    if tokens.len() > 1 && tokens[tokens.len() - 1].contains(':') {
+        let token = tokens.pop().unwrap();
+        time = match parse_time_of_day(token) {
+            Some(v) => Some(v),
+            None => {
+                return Err(ParseTodoError::new(&format!(
+                    "Error parsing date {}: {} is not a time of day, expected HH:MM",
+                    s, token
+                )))
+            }
+        };
+    }
+
+    let expression = tokens.join(" ");
+    let date = match parse_date_expression(&expression, now) {
+        Ok(v) => v,
+        Err(err) => {
+            return Err(ParseTodoError::new(&format!(
+                "Error parsing date {}: {}",
+                s, err
+            )))
+        }
+    };
+
+    match time {
+        Some(v) => Ok(Some(date.date().and_time(v))),
+        None => Ok(Some(date)),
+    }
+}
+
+fn parse_time_of_day(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M").ok()
+}
+
+fn parse_date_expression(s: &str, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+    let today = now.date();
+    let midnight = |d: NaiveDate| d.and_time(NaiveTime::MIN);
+
+    if let Ok(Some(v)) = parse_date_result(s) {
+        return Ok(v);
+    }
+    if let Some(v) = parse_time_of_day(s) {
+        return Ok(today.and_time(v));
+    }
+
+    match s {
+        "" => return Err(String::from("no date given")),
+        "today" => return Ok(midnight(today)),
+        "tomorrow" => return Ok(midnight(today + ChronoDuration::days(1))),
+        "yesterday" => return Ok(midnight(today - ChronoDuration::days(1))),
+        "eow" => {
+            let days_left = 6 - today.weekday().num_days_from_monday();
+            return Ok(midnight(today + ChronoDuration::days(days_left as i64)));
+        }
+        "eom" => return Ok(midnight(end_of_month(today))),
+        "eoy" => {
+            return Ok(midnight(
+                NaiveDate::from_ymd_opt(today.year(), 12, 31).unwrap(),
+            ))
+        }
+        _ => {}
+    }
+
+    if let Some(rest) = s.strip_prefix("next ") {
+        let weekday = parse_weekday(rest.trim())?;
+        return Ok(midnight(next_weekday(today, weekday, false)));
+    }
+    if let Ok(weekday) = parse_weekday(s) {
+        return Ok(midnight(next_weekday(today, weekday, true)));
+    }
+
+    parse_date_offset(s, now)
+}
+
+fn end_of_month(d: NaiveDate) -> NaiveDate {
+    let first = NaiveDate::from_ymd_opt(d.year(), d.month(), 1).unwrap();
+    first + Months::new(1) - ChronoDuration::days(1)
+}
+
+// Weekdays can be abbreviated down to their first three letters.
+fn parse_weekday(s: &str) -> Result<Weekday, String> {
+    let weekdays = [
+        ("monday", Weekday::Mon),
+        ("tuesday", Weekday::Tue),
+        ("wednesday", Weekday::Wed),
+        ("thursday", Weekday::Thu),
+        ("friday", Weekday::Fri),
+        ("saturday", Weekday::Sat),
+        ("sunday", Weekday::Sun),
+    ];
+    if s.len() >= 3 {
+        for (name, weekday) in weekdays.iter() {
+            if name.starts_with(s) {
+                return Ok(*weekday);
+            }
+        }
+    }
+    Err(format!("unknown weekday {}", s))
+}
+
+// The next day with the given weekday, today only counts if allowed.
+fn next_weekday(today: NaiveDate, weekday: Weekday, include_today: bool) -> NaiveDate {
+    let current = today.weekday().num_days_from_monday() as i64;
+    let target = weekday.num_days_from_monday() as i64;
+    let mut days = (target - current).rem_euclid(7);
+    if days == 0 && !include_today {
+        days = 7;
+    }
+    today + ChronoDuration::days(days)
+}
+
+fn unknown_date(s: &str) -> String {
+    format!(
+        "{} is not a known date, try e.g. today, fri, next mon, +3d, eom or YYYY-MM-DD",
+        s
+    )
+}
+
+// Offsets like +3d, 2w or -1m, hours keep the time of day, the other units
+// give a date.
+fn parse_date_offset(s: &str, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+    let (negative, rest) = if let Some(v) = s.strip_prefix('-') {
+        (true, v)
+    } else {
+        (false, s.strip_prefix('+').unwrap_or(s))
+    };
+
+    let unit = match rest.chars().last() {
+        Some(v) if v.is_ascii_alphabetic() => v,
+        _ => return Err(unknown_date(s)),
+    };
+    let amount = &rest[..rest.len() - 1];
+    if amount.is_empty() || !amount.chars().all(|c| c.is_ascii_digit()) {
+        return Err(unknown_date(s));
+    }
+    let amount = match amount.parse::<u32>() {
+        Ok(v) => v,
+        Err(err) => return Err(format!("{} is not a valid amount: {}", amount, err)),
+    };
+
+    let signed = if negative {
+        -(amount as i64)
+    } else {
+        amount as i64
+    };
+    let today = now.date();
+    let result = match unit {
+        'h' => {
+            return match now.checked_add_signed(ChronoDuration::hours(signed)) {
+                Some(v) => Ok(v),
+                None => Err(format!("{} is out of range", s)),
+            }
+        }
+        'd' => today.checked_add_signed(ChronoDuration::days(signed)),
+        'w' => today.checked_add_signed(ChronoDuration::weeks(signed)),
+        'm' if negative => today.checked_sub_months(Months::new(amount)),
+        'm' => today.checked_add_months(Months::new(amount)),
+        'y' => match amount.checked_mul(12) {
+            Some(v) if negative => today.checked_sub_months(Months::new(v)),
+            Some(v) => today.checked_add_months(Months::new(v)),
+            None => None,
+        },
+        _ => {
+            return Err(format!(
+                "unknown unit {} in {}, use h, d, w, m or y",
+                unit, s
+            ))
+        }
+    };
+
+    match result {
+        Some(v) => Ok(v.and_time(NaiveTime::MIN)),
+        None => Err(format!("{} is out of range", s)),
+    }
+}
+
+// Parses a recurrence rule: daily, weekly, monthly, yearly, every 3 days,
+// every 2 weeks, every mon,thu. A leading "after" repeats relative to the
+// day the todo was done (after 10 days), otherwise the due date keeps its
+// schedule. An empty string or "none" removes the rule.
+pub fn parse_recurrence(s: &str) -> Result<Option<Recurrence>, ParseTodoError> {
+    let s = s.trim();
+    if s.is_empty() || s.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    let error = |msg: &str| {
+        ParseTodoError::new(&format!(
+            "Error parsing recurrence {}: {}, expected e.g. daily, every 3 days, every mon,thu or after 2 weeks",
+            s, msg
+        ))
+    };
+
+    let lower = s.to_lowercase();
+    let mut tokens: Vec<&str> = lower.split_whitespace().collect();
+    let after_completion = tokens[0] == "after";
+    if after_completion || tokens[0] == "every" {
+        tokens.remove(0);
+    }
+
+    let interval = match tokens[..] {
+        [] => return Err(error("no interval given")),
+        ["daily"] => RecurrenceInterval::Days(1),
+        ["weekly"] => RecurrenceInterval::Weeks(1),
+        ["monthly"] => RecurrenceInterval::Months(1),
+        ["yearly"] => RecurrenceInterval::Years(1),
+        [number, unit] if number.chars().all(|c| c.is_ascii_digit()) => {
+            let n = match number.parse::<u32>() {
+                Ok(v) if v > 0 => v,
+                _ => return Err(error(&format!("{} is not a valid count", number))),
+            };
+            match parse_recurrence_unit(unit, n) {
+                Some(v) => v,
+                None => {
+                    return Err(error(&format!(
+                        "unknown unit {}, use days, weeks, months or years",
+                        unit
+                    )))
+                }
+            }
+        }
+        _ => match parse_recurrence_unit(&tokens.join(" "), 1) {
+            Some(v) => v,
+            None => match parse_recurrence_weekdays(&tokens) {
+                Ok(v) => RecurrenceInterval::Weekdays(v),
+                Err(err) => return Err(error(&err)),
+            },
+        },
+    };
+
+    Ok(Some(Recurrence {
+        interval,
+        after_completion,
+    }))
+}
+
+// Weekdays separated by commas or spaces: mon,thu or mon thu.
+fn parse_recurrence_weekdays(tokens: &[&str]) -> Result<Vec<Weekday>, String> {
+    let mut weekdays = Vec::new();
+    for name in tokens.join(",").split(',').filter(|v| !v.is_empty()) {
+        let weekday = match parse_weekday(name) {
+            Ok(v) => v,
+            Err(_) => return Err(format!("{} is neither an interval nor a weekday", name)),
+        };
+        if !weekdays.contains(&weekday) {
+            weekdays.push(weekday);
+        }
+    }
+    weekdays.sort_by_key(|v| v.num_days_from_monday());
+    Ok(weekdays)
+}
+
+fn parse_recurrence_unit(unit: &str, n: u32) -> Option<RecurrenceInterval> {
+    match unit.strip_suffix('s').unwrap_or(unit) {
+        "day" => Some(RecurrenceInterval::Days(n)),
+        "week" => Some(RecurrenceInterval::Weeks(n)),
+        "month" => Some(RecurrenceInterval::Months(n)),
+        "year" => Some(RecurrenceInterval::Years(n)),
+        _ => None,
+    }
+}
+
+// Writes the rule in a form parse_recurrence reads back.
+pub fn recurrence_to_string(r: &Recurrence) -> String {
+    let (n, unit) = match &r.interval {
+        RecurrenceInterval::Days(n) => (*n, "day"),
+        RecurrenceInterval::Weeks(n) => (*n, "week"),
+        RecurrenceInterval::Months(n) => (*n, "month"),
+        RecurrenceInterval::Years(n) => (*n, "year"),
+        RecurrenceInterval::Weekdays(days) => {
+            let days = days
+                .iter()
+                .map(|v| v.to_string().to_lowercase())
+                .collect::<Vec<String>>()
+                .join(",");
+            let prefix = if r.after_completion { "after" } else { "every" };
+            return format!("{} {}", prefix, days);
+        }
+    };
+
+    let plural = if n == 1 { "" } else { "s" };
+    match (r.after_completion, n) {
+        (true, _) => format!("after {} {}{}", n, unit, plural),
+        (false, 1) if unit == "day" => String::from("daily"),
+        (false, 1) => format!("{}ly", unit),
+        (false, _) => format!("every {} {}{}", n, unit, plural),
+    }
+}
+
+pub fn option_recurrence_to_string(r: &Option<Recurrence>) -> String {
+    match r {
+        Some(v) => recurrence_to_string(v),
+        None => String::from(""),
+    }
+}
+
+fn push_word(list: &mut String, word: &str) {
+    if !list.is_empty() {
+        list.push(' ');
+    }
+    list.push_str(word);
+}
+
+// Applies one word of a quick-add description to the todo: +project,
+// @category, !priority, est:, act:, due:, sched: and #color. Returns false
+// for words that belong to the description.
+pub fn parse_quick_add_word(
+    todo: &mut Todo,
+    word: &str,
+    work_day: Duration,
+) -> Result<bool, ParseTodoError> {
+    let mut chars = word.chars();
+    let first = chars.next();
+    let value = chars.as_str();
+    if value.is_empty() {
+        return Ok(false);
+    }
+    match first {
+        Some('+') => {
+            push_word(&mut todo.projects, value);
+            return Ok(true);
+        }
+        Some('@') => {
+            push_word(&mut todo.categories, value);
+            return Ok(true);
+        }
+        Some('!') => {
+            if let Ok(v) = value.parse::<isize>() {
+                todo.priority = v;
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+        // Only known colors, so #123 stays in the description.
+        Some('#') => {
+            if let Some(color) = parse_color(value) {
+                todo.color = color;
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+        _ => {}
+    }
+
+    let (key, value) = match word.split_once(':') {
+        Some(v) if !v.1.is_empty() => v,
+        _ => return Ok(false),
+    };
+    match key {
+        "est" => todo.time_estimated = parse_duration_input(value, work_day)?,
+        "act" => todo.time_actual = parse_duration_input(value, work_day)?,
+        "due" => todo.due = parse_date_input(value)?,
+        "sched" => todo.scheduled = parse_date_input(value)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// Sets description and metadata from a description with inline metadata,
+// like `Fix login +web @bug !3 est:2h due:fri #red`. The metadata words are
+// taken out of the description.
+pub fn parse_quick_add(todo: &mut Todo, s: &str, work_day: Duration) -> Result<(), ParseTodoError> {
+    let mut description = Vec::new();
+    for word in s.split_whitespace() {
+        if !parse_quick_add_word(todo, word, work_day)? {
+            description.push(word);
+        }
+    }
+    todo.description = parse_string(&description.join(" "));
+    Ok(())
+}
+
+const SESSION_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+// Sessions are stored as start/end pairs separated by commas, the end is
+// left empty while the timer is running.
+pub fn sessions_to_string(sessions: &[WorkSession]) -> String {
+    sessions
+        .iter()
+        .map(|session| {
+            let end = match session.end {
+                Some(v) => v.format(SESSION_FORMAT).to_string(),
+                None => String::from(""),
+            };
+            format!("{}/{}", session.start.format(SESSION_FORMAT), end)
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+pub fn parse_sessions(s: &str) -> Result<Vec<WorkSession>, ParseTodoError> {
+    let mut sessions = Vec::new();
+    if s.is_empty() {
+        return Ok(sessions);
+    }
+
+    let parse_time = |t: &str| match NaiveDateTime::parse_from_str(t, SESSION_FORMAT) {
+        Ok(v) => Ok(v),
+        Err(err) => Err(ParseTodoError::new(&format!(
+            "Error parsing session time {}: {}",
+            t, err
+        ))),
+    };
+
+    for session in s.split(',') {
+        let (start, end) = match session.split_once('/') {
+            Some(v) => v,
+            None => {
+                return Err(ParseTodoError::new(&format!(
+                    "Error parsing session {}: expected start/end",
+                    session
+                )))
+            }
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(parse_time(end)?)
+        };
+        sessions.push(WorkSession {
+            start: parse_time(start)?,
+            end,
+        });
+    }
+    Ok(sessions)
+}
+
 impl FromStr for Todo {
     type Err = ParseTodoError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let split: Vec<&str> = s.split('\t').collect();
-        if split.len() != 9 {
-            return Err(ParseTodoError::new("Not nine fields long"));
+        if split.len() < 9 {
+            return Err(ParseTodoError::new("Less than nine fields long"));
         }
+        // Fields added later are optional, so older files keep working.
+        let optional = |i: usize| split.get(i).copied().unwrap_or("");
 
         let new_todo = Todo {
             id: parse_usize(split[0])?,
@@ -66,6 +687,14 @@ impl FromStr for Todo {
             time_actual: parse_duration_result(split[6])?,
             status: split[7].parse()?,
             color: string_to_color_or_white(split[8]),
+            due: parse_date_result(optional(9))?,
+            scheduled: parse_date_result(optional(10))?,
+            sessions: parse_sessions(optional(11))?,
+            parent: parse_option_usize(optional(12))?,
+            depends: parse_usize_list(optional(13))?,
+            recurrence: parse_recurrence(optional(14))?,
+            notes: unescape_notes(optional(15)),
+            blocked: false,
         };
         Ok(new_todo)
     }
@@ -103,6 +732,17 @@ pub fn color_to_string(c: Color) -> String {
     }
 }
 
+// One of the known colors, in any case.
+pub fn parse_color(s: &str) -> Option<Color> {
+    let colors = [
+        "Black", "Red", "Green", "Yellow", "Blue", "Purple", "Cyan", "White",
+    ];
+    colors
+        .iter()
+        .find(|c| c.eq_ignore_ascii_case(s))
+        .map(|name| string_to_color_or_white(name))
+}
+
 pub fn string_to_color_or_white(s: &str) -> Color {
     match s {
         "Black" => Color::Black,
@@ -116,3 +756,101 @@ pub fn string_to_color_or_white(s: &str) -> Color {
         _ => Color::White,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Wednesday.
+    fn now() -> NaiveDateTime {
+        at("2024-05-15 10:00")
+    }
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn date(s: &str) -> Option<NaiveDateTime> {
+        parse_date_input_at(s, now()).unwrap()
+    }
+
+    fn error(s: &str) -> String {
+        parse_date_input_at(s, now()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn relative_days() {
+        assert_eq!(date("today"), Some(at("2024-05-15 00:00")));
+        assert_eq!(date("Tomorrow"), Some(at("2024-05-16 00:00")));
+        assert_eq!(date("yesterday"), Some(at("2024-05-14 00:00")));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(date("fri"), Some(at("2024-05-17 00:00")));
+        assert_eq!(date("friday"), Some(at("2024-05-17 00:00")));
+        assert_eq!(date("mon"), Some(at("2024-05-20 00:00")));
+        assert_eq!(date("next mon"), Some(at("2024-05-20 00:00")));
+        // Today counts, next skips to the week after.
+        assert_eq!(date("wed"), Some(at("2024-05-15 00:00")));
+        assert_eq!(date("next wed"), Some(at("2024-05-22 00:00")));
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(date("+3d"), Some(at("2024-05-18 00:00")));
+        assert_eq!(date("2w"), Some(at("2024-05-29 00:00")));
+        assert_eq!(date("-1m"), Some(at("2024-04-15 00:00")));
+        assert_eq!(date("+1y"), Some(at("2025-05-15 00:00")));
+        assert_eq!(date("4h"), Some(at("2024-05-15 14:00")));
+    }
+
+    #[test]
+    fn ends_of_periods() {
+        assert_eq!(date("eow"), Some(at("2024-05-19 00:00")));
+        assert_eq!(date("eom"), Some(at("2024-05-31 00:00")));
+        assert_eq!(date("eoy"), Some(at("2024-12-31 00:00")));
+        assert_eq!(
+            parse_date_input_at("eom", at("2024-02-10 08:00")).unwrap(),
+            Some(at("2024-02-29 00:00"))
+        );
+    }
+
+    #[test]
+    fn times_of_day() {
+        assert_eq!(date("14:30"), Some(at("2024-05-15 14:30")));
+        assert_eq!(date("fri 9:00"), Some(at("2024-05-17 09:00")));
+        assert_eq!(date("tomorrow 18:15"), Some(at("2024-05-16 18:15")));
+    }
+
+    #[test]
+    fn absolute_dates_and_none() {
+        assert_eq!(date("2024-06-01"), Some(at("2024-06-01 00:00")));
+        assert_eq!(date("2024-06-01 14:30"), Some(at("2024-06-01 14:30")));
+        assert_eq!(date("2024-06-01T14:30"), Some(at("2024-06-01 14:30")));
+        assert_eq!(date(""), None);
+        assert_eq!(date("none"), None);
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(
+            error("later"),
+            "Parsing error: Error parsing date later: later is not a known date, try e.g. \
+             today, fri, next mon, +3d, eom or YYYY-MM-DD"
+        );
+        assert_eq!(
+            error("fri 25:00"),
+            "Parsing error: Error parsing date fri 25:00: 25:00 is not a time of day, \
+             expected HH:MM"
+        );
+        assert_eq!(
+            error("3x"),
+            "Parsing error: Error parsing date 3x: unknown unit x in 3x, use h, d, w, m or y"
+        );
+        assert_eq!(
+            error("next someday"),
+            "Parsing error: Error parsing date next someday: unknown weekday someday"
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use chrono::{Datelike, Duration as ChronoDuration, Months, NaiveDate, NaiveTime};
+use std::collections::HashMap;
+
+use super::*;
+
+// The date count intervals after start, None if it is out of range.
+fn step(interval: &RecurrenceInterval, start: NaiveDate, count: u32) -> Option<NaiveDate> {
+    match interval {
+        RecurrenceInterval::Days(n) => {
+            start.checked_add_signed(ChronoDuration::days(n.checked_mul(count)? as i64))
+        }
+        RecurrenceInterval::Weeks(n) => {
+            start.checked_add_signed(ChronoDuration::weeks(n.checked_mul(count)? as i64))
+        }
+        RecurrenceInterval::Months(n) => {
+            start.checked_add_months(Months::new(n.checked_mul(count)?))
+        }
+        RecurrenceInterval::Years(n) => {
+            start.checked_add_months(Months::new(n.checked_mul(count)?.checked_mul(12)?))
+        }
+        RecurrenceInterval::Weekdays(days) => {
+            // The first of the weekdays after start.
+            (1..=7)
+                .map(|i| start + ChronoDuration::days(i))
+                .find(|d| days.contains(&d.weekday()))
+        }
+    }
+}
+
+// The next date the todo is due again. A fixed schedule counts from the
+// previous date and skips the dates that have already passed, otherwise it
+// counts from today, the day the todo was done.
+pub fn next_occurrence(
+    recurrence: &Recurrence,
+    previous: NaiveDate,
+    today: NaiveDate,
+) -> Option<NaiveDate> {
+    if recurrence.after_completion {
+        return step(&recurrence.interval, today, 1);
+    }
+
+    if let RecurrenceInterval::Weekdays(_) = recurrence.interval {
+        return step(&recurrence.interval, previous.max(today), 1);
+    }
+    let mut count = 1;
+    loop {
+        let next = step(&recurrence.interval, previous, count)?;
+        if next > today {
+            return Some(next);
+        }
+        count += 1;
+    }
+}
+
+// Builds the next instance of a recurring todo that was just done, with the
+// due and scheduled dates moved along. A todo without dates gets a due date.
+// The caller gives it a new id.
+pub fn next_instance(todo: &Todo, now: NaiveDateTime) -> Option<Todo> {
+    let recurrence = todo.get_recurrence()?;
+    let previous = todo
+        .get_due()
+        .or_else(|| todo.get_scheduled())
+        .unwrap_or(now);
+    let next = next_occurrence(recurrence, previous.date(), now.date())?;
+    let shift = next.and_time(previous.time()) - previous;
+
+    let mut new_todo = todo.clone();
+    new_todo.set_status(TodoStatus::New).unwrap();
+    new_todo.set_sessions(Vec::new());
+    new_todo.set_time_actual(None);
+    if todo.get_due().is_none() && todo.get_scheduled().is_none() {
+        new_todo.set_due(Some(next.and_time(NaiveTime::MIN)));
+    } else {
+        new_todo.set_due(todo.get_due().map(|d| d + shift));
+        new_todo.set_scheduled(todo.get_scheduled().map(|d| d + shift));
+    }
+    Some(new_todo)
+}
+
+// Marks the todos as done and stops their running sessions, as t do does.
+// Returns them together with the next instances of the recurring ones,
+// whose ids count up from next_id.
+pub fn finish_todos(todos: &[Todo], now: NaiveDateTime, next_id: usize) -> (Vec<Todo>, Vec<Todo>) {
+    let mut next_id = next_id;
+    let mut done = Vec::new();
+    let mut next_instances = Vec::new();
+    for todo in todos.iter() {
+        let mut todo = todo.clone();
+        todo.set_status(TodoStatus::Done).unwrap();
+        todo.stop_session(now);
+
+        // A recurring todo comes back as a new one, the rule moves along.
+        if let Some(mut next) = next_instance(&todo, now) {
+            next.set_id(next_id);
+            next_id += 1;
+            next_instances.push((todo.get_id(), next));
+        }
+        todo.set_recurrence(None);
+        done.push(todo);
+    }
+
+    // Subtasks that come back belong to the next instance of their parent.
+    let new_ids: HashMap<usize, usize> = next_instances
+        .iter()
+        .map(|(old, todo)| (*old, todo.get_id()))
+        .collect();
+    let next_instances = next_instances
+        .into_iter()
+        .map(|(_, mut todo)| {
+            if let Some(parent) = todo.get_parent() {
+                todo.set_parent(Some(*new_ids.get(&parent).unwrap_or(&parent)));
+            }
+            todo
+        })
+        .collect();
+    (done, next_instances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn next(rule: &str, previous: &str, today: &str) -> Option<NaiveDate> {
+        let recurrence = parse_recurrence(rule).unwrap().unwrap();
+        next_occurrence(&recurrence, day(previous), day(today))
+    }
+
+    fn recurring(rule: &str, due: Option<&str>, scheduled: Option<&str>) -> Todo {
+        let mut todo = Todo::new_with_id(3);
+        todo.set_recurrence_from_string(rule).unwrap();
+        todo.set_due(due.map(at));
+        todo.set_scheduled(scheduled.map(at));
+        todo.set_status(TodoStatus::Done).unwrap();
+        todo
+    }
+
+    #[test]
+    fn weekday_lists() {
+        // 2024-05-15 is a Wednesday.
+        assert_eq!(
+            next("every mon,thu", "2024-05-15", "2024-05-15"),
+            Some(day("2024-05-16"))
+        );
+        assert_eq!(
+            next("every mon,thu", "2024-05-16", "2024-05-16"),
+            Some(day("2024-05-20"))
+        );
+        // Done late, the next weekday after today.
+        assert_eq!(
+            next("every mon,thu", "2024-05-02", "2024-05-15"),
+            Some(day("2024-05-16"))
+        );
+        // Done early, the next weekday after the due date.
+        assert_eq!(
+            next("every mon,thu", "2024-05-20", "2024-05-15"),
+            Some(day("2024-05-23"))
+        );
+        assert_eq!(
+            next("every sun", "2024-05-19", "2024-05-19"),
+            Some(day("2024-05-26"))
+        );
+    }
+
+    #[test]
+    fn month_end_rolls_back() {
+        assert_eq!(
+            next("monthly", "2024-01-31", "2024-01-31"),
+            Some(day("2024-02-29"))
+        );
+        assert_eq!(
+            next("monthly", "2023-01-31", "2023-01-31"),
+            Some(day("2023-02-28"))
+        );
+        assert_eq!(
+            next("monthly", "2024-03-31", "2024-03-31"),
+            Some(day("2024-04-30"))
+        );
+        // Skipped months count from the first date, not the shortened one.
+        assert_eq!(
+            next("monthly", "2024-01-31", "2024-03-05"),
+            Some(day("2024-03-31"))
+        );
+        assert_eq!(
+            next("yearly", "2024-02-29", "2024-02-29"),
+            Some(day("2025-02-28"))
+        );
+    }
+
+    #[test]
+    fn fixed_schedule_skips_passed_dates() {
+        assert_eq!(
+            next("every 2 days", "2024-05-15", "2024-05-15"),
+            Some(day("2024-05-17"))
+        );
+        assert_eq!(
+            next("weekly", "2024-05-01", "2024-05-14"),
+            Some(day("2024-05-15"))
+        );
+        assert_eq!(
+            next("weekly", "2024-05-01", "2024-05-15"),
+            Some(day("2024-05-22"))
+        );
+        // Done early, the schedule still counts from the due date.
+        assert_eq!(
+            next("weekly", "2024-05-20", "2024-05-15"),
+            Some(day("2024-05-27"))
+        );
+    }
+
+    #[test]
+    fn after_completion_counts_from_today() {
+        assert_eq!(
+            next("after 10 days", "2024-05-01", "2024-05-15"),
+            Some(day("2024-05-25"))
+        );
+        assert_eq!(
+            next("after 2 weeks", "2024-05-20", "2024-05-15"),
+            Some(day("2024-05-29"))
+        );
+        assert_eq!(
+            next("after 1 month", "2024-01-01", "2024-01-31"),
+            Some(day("2024-02-29"))
+        );
+    }
+
+    #[test]
+    fn next_instance_moves_both_dates() {
+        let mut todo = recurring("weekly", Some("2024-05-10 09:00"), Some("2024-05-08 14:30"));
+        todo.set_time_actual(Some(Duration::from_secs(90 * 60)));
+        let new_todo = next_instance(&todo, at("2024-05-15 10:00")).unwrap();
+
+        assert_eq!(new_todo.get_due(), Some(at("2024-05-17 09:00")));
+        assert_eq!(new_todo.get_scheduled(), Some(at("2024-05-15 14:30")));
+        assert_eq!(new_todo.get_status(), TodoStatus::New);
+        assert_eq!(new_todo.get_time_actual(), None);
+        assert!(new_todo.get_sessions().is_empty());
+        assert_eq!(new_todo.get_recurrence(), todo.get_recurrence());
+    }
+
+    #[test]
+    fn next_instance_after_completion() {
+        let todo = recurring("after 3 days", Some("2024-05-10 09:00"), None);
+        let new_todo = next_instance(&todo, at("2024-05-15 10:00")).unwrap();
+        assert_eq!(new_todo.get_due(), Some(at("2024-05-18 09:00")));
+        assert_eq!(new_todo.get_scheduled(), None);
+
+        // Without a due date the scheduled date is the one moved along.
+        let todo = recurring("after 3 days", None, Some("2024-05-10 09:00"));
+        let new_todo = next_instance(&todo, at("2024-05-15 10:00")).unwrap();
+        assert_eq!(new_todo.get_due(), None);
+        assert_eq!(new_todo.get_scheduled(), Some(at("2024-05-18 09:00")));
+    }
+
+    #[test]
+    fn next_instance_without_dates_gets_a_due_date() {
+        let todo = recurring("daily", None, None);
+        let new_todo = next_instance(&todo, at("2024-05-15 10:00")).unwrap();
+        assert_eq!(new_todo.get_due(), Some(at("2024-05-16 00:00")));
+        assert_eq!(new_todo.get_scheduled(), None);
+
+        let mut todo = recurring("daily", None, None);
+        todo.set_recurrence(None);
+        assert!(next_instance(&todo, at("2024-05-15 10:00")).is_none());
+    }
+
+    #[test]
+    fn finishing_stops_the_session_and_brings_recurring_todos_back() {
+        let mut parent = recurring("weekly", Some("2024-05-10 09:00"), None);
+        parent.set_status(TodoStatus::Doing).unwrap();
+        parent.start_session(at("2024-05-15 09:00")).unwrap();
+        let mut child = recurring("weekly", Some("2024-05-10 09:00"), None);
+        child.set_id(4);
+        child.set_parent(Some(3));
+        child.set_status(TodoStatus::New).unwrap();
+        let mut plain = Todo::new_with_id(5);
+        plain.set_parent(Some(3));
+
+        let (done, next) = finish_todos(&[parent, child, plain], at("2024-05-15 10:00"), 8);
+        assert_eq!(done.len(), 3);
+        for todo in done.iter() {
+            assert_eq!(todo.get_status(), TodoStatus::Done);
+            assert_eq!(todo.get_recurrence(), None);
+            assert!(todo.running_session().is_none());
+        }
+        assert_eq!(done[0].get_sessions()[0].end, Some(at("2024-05-15 10:00")));
+
+        let ids: Vec<usize> = next.iter().map(|todo| todo.get_id()).collect();
+        assert_eq!(ids, vec![8, 9]);
+        assert_eq!(next[0].get_parent(), None);
+        // The next instance of the subtask belongs to the next parent.
+        assert_eq!(next[1].get_parent(), Some(8));
+        assert_eq!(next[1].get_due(), Some(at("2024-05-17 09:00")));
+    }
+}
@@ -0,0 +1,196 @@
+use ansi_term::{Color, Style};
+use chrono::NaiveDateTime;
+use std::time::Duration;
+
+use super::parse::*;
+use super::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Status,
+    Priority,
+    Description,
+    Projects,
+    Categories,
+    Due,
+    Scheduled,
+    Estimated,
+    Actual,
+    Difference,
+}
+
+impl Column {
+    fn from_name(s: &str) -> Option<Column> {
+        match s {
+            "id" => Some(Column::Id),
+            "status" | "stat" => Some(Column::Status),
+            "prio" | "priority" => Some(Column::Priority),
+            "desc" | "description" => Some(Column::Description),
+            "proj" | "project" | "projects" => Some(Column::Projects),
+            "cat" | "category" | "categories" => Some(Column::Categories),
+            "due" => Some(Column::Due),
+            "sched" | "scheduled" => Some(Column::Scheduled),
+            "est" | "estimated" => Some(Column::Estimated),
+            "act" | "actual" => Some(Column::Actual),
+            "diff" | "difference" => Some(Column::Difference),
+            _ => None,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Id => "ID",
+            Column::Status => "Status",
+            Column::Priority => "Prio",
+            Column::Description => "Description",
+            Column::Projects => "Projects",
+            Column::Categories => "Categories",
+            Column::Due => "Due",
+            Column::Scheduled => "Scheduled",
+            Column::Estimated => "Estimated",
+            Column::Actual => "Actual",
+            Column::Difference => "Difference",
+        }
+    }
+}
+
+// How todos are shown in tables: which columns, in which order, whether
+// with colors and how dates look.
+#[derive(Debug, Clone)]
+pub struct TableStyle {
+    pub columns: Vec<Column>,
+    pub color: bool,
+    pub overdue_color: Color,
+    pub today_color: Color,
+    pub date_format: String,
+    pub time_format: String,
+}
+
+impl Default for TableStyle {
+    fn default() -> TableStyle {
+        TableStyle {
+            columns: vec![
+                Column::Id,
+                Column::Status,
+                Column::Priority,
+                Column::Description,
+                Column::Projects,
+                Column::Categories,
+                Column::Due,
+                Column::Scheduled,
+                Column::Estimated,
+                Column::Actual,
+                Column::Difference,
+            ],
+            color: true,
+            overdue_color: Color::Red,
+            today_color: Color::Yellow,
+            date_format: String::from("%Y-%m-%d"),
+            time_format: String::from("%H:%M"),
+        }
+    }
+}
+
+// A comma separated list of column names like id,prio,desc,due.
+pub fn parse_columns(s: &str) -> Result<Vec<Column>, String> {
+    let mut columns = Vec::new();
+    for name in s.split(',').map(|v| v.trim().to_lowercase()) {
+        match Column::from_name(&name) {
+            Some(v) => columns.push(v),
+            None => {
+                return Err(format!(
+                    "Unknown column {}, use id, status, prio, desc, proj, cat, due, sched, est, \
+                     act or diff",
+                    name
+                ))
+            }
+        }
+    }
+    Ok(columns)
+}
+
+// Difference between actual and estimated time, with actual/estimated
+// behind it once both are known.
+fn difference(todo: &Todo) -> String {
+    let estimated = todo.get_time_estimated().unwrap_or_default();
+    let actual = todo.get_time_actual().unwrap_or_default();
+    if estimated.as_secs() == 0 || actual.as_secs() == 0 {
+        return duration_to_human_string(Duration::from_secs(0));
+    }
+    format!(
+        "{}/{:.2}",
+        duration_to_human_string(actual.abs_diff(estimated)),
+        actual.as_secs() as f64 / estimated.as_secs() as f64
+    )
+}
+
+impl TableStyle {
+    pub fn header(&self) -> String {
+        let titles: Vec<&str> = self.columns.iter().map(|v| v.title()).collect();
+        format!("{}\n", titles.join("\t"))
+    }
+
+    pub fn date(&self, d: NaiveDateTime) -> String {
+        if is_date_only(d) {
+            d.format(&self.date_format).to_string()
+        } else {
+            d.format(&format!("{} {}", self.date_format, self.time_format))
+                .to_string()
+        }
+    }
+
+    fn option_date(&self, d: Option<NaiveDateTime>) -> String {
+        d.map(|v| self.date(v)).unwrap_or_default()
+    }
+
+    // One line of the table, without the newline. `now` decides which due
+    // dates are overdue, the same for every row of a table.
+    pub fn row(&self, todo: &Todo, now: NaiveDateTime) -> String {
+        let print_color = if todo.done() {
+            Color::Fixed(8)
+        } else {
+            todo.get_color()
+        };
+        let due_style = match todo.due_state(now) {
+            DueState::Overdue => self.overdue_color.bold(),
+            DueState::Today => self.today_color.bold(),
+            _ => Style::from(print_color),
+        };
+
+        let cells: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let (style, value) = match column {
+                    Column::Id => (Style::from(print_color), todo.get_id().to_string()),
+                    Column::Status => (Style::from(print_color), todo.get_status().to_string()),
+                    Column::Priority => (Style::from(print_color), todo.get_priority().to_string()),
+                    Column::Description => (Style::from(print_color), todo.get_description()),
+                    Column::Projects => (Style::from(print_color), todo.get_projects()),
+                    Column::Categories => (Style::from(print_color), todo.get_categories()),
+                    Column::Due => (due_style, self.option_date(todo.get_due())),
+                    Column::Scheduled => (
+                        Style::from(print_color),
+                        self.option_date(todo.get_scheduled()),
+                    ),
+                    Column::Estimated => (
+                        Style::from(print_color),
+                        duration_to_human_string(todo.get_time_estimated().unwrap_or_default()),
+                    ),
+                    Column::Actual => (
+                        Style::from(print_color),
+                        duration_to_human_string(todo.get_time_actual().unwrap_or_default()),
+                    ),
+                    Column::Difference => (Style::from(print_color), difference(todo)),
+                };
+                if self.color {
+                    style.paint(value).to_string()
+                } else {
+                    value
+                }
+            })
+            .collect();
+        cells.join("\t")
+    }
+}
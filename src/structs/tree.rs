@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use super::deps::*;
+use super::*;
+
+// Estimated and actual time of a todo plus all of its descendants.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Rollup {
+    pub estimated: Duration,
+    pub actual: Duration,
+    pub children_done: usize,
+    pub children: usize,
+}
+
+impl Rollup {
+    pub fn percent_done(&self) -> usize {
+        if self.children == 0 {
+            return 0;
+        }
+        self.children_done * 100 / self.children
+    }
+}
+
+pub fn children_of(todos: &[Todo], id: usize) -> Vec<&Todo> {
+    todos
+        .iter()
+        .filter(|todo| todo.get_parent() == Some(id))
+        .collect()
+}
+
+// All ids below the given todo, children before grandchildren.
+pub fn descendant_ids(todos: &[Todo], id: usize) -> Vec<usize> {
+    let mut ids = Vec::new();
+    let mut queue = vec![id];
+    while let Some(current) = queue.pop() {
+        for child in children_of(todos, current) {
+            if child.get_id() != id && !ids.contains(&child.get_id()) {
+                ids.push(child.get_id());
+                queue.push(child.get_id());
+            }
+        }
+    }
+    ids
+}
+
+// The ids together with the ids of their subtasks, each once.
+pub fn with_descendants(todos: &[Todo], ids: &[usize]) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    let mut all_ids = Vec::new();
+    for id in ids {
+        for id in std::iter::once(*id).chain(descendant_ids(todos, *id)) {
+            if seen.insert(id) {
+                all_ids.push(id);
+            }
+        }
+    }
+    all_ids
+}
+
+// Whether making `parent` the parent of `id` would create a cycle.
+pub fn would_create_cycle(todos: &[Todo], id: usize, parent: usize) -> bool {
+    parent == id || descendant_ids(todos, id).contains(&parent)
+}
+
+// None if the todo has no children.
+pub fn rollup(todos: &[Todo], id: usize) -> Option<Rollup> {
+    let descendants = descendant_ids(todos, id);
+    if descendants.is_empty() {
+        return None;
+    }
+
+    let mut r = Rollup::default();
+    for todo in todos.iter() {
+        if todo.get_id() == id || descendants.contains(&todo.get_id()) {
+            r.estimated += todo.get_time_estimated().unwrap_or_default();
+            r.actual += todo.get_time_actual().unwrap_or_default();
+        }
+        if descendants.contains(&todo.get_id()) {
+            r.children += 1;
+            if todo.done() {
+                r.children_done += 1;
+            }
+        }
+    }
+    Some(r)
+}
+
+// Orders the todos as a tree with their depth, keeping the given order among
+// siblings. Todos whose parent is not in the list are shown at the top level.
+pub fn tree_order<'a>(todos: &[&'a Todo]) -> Vec<(&'a Todo, usize)> {
+    let ids: HashSet<usize> = todos.iter().map(|todo| todo.get_id()).collect();
+    let mut ordered = Vec::new();
+    let mut visited = HashSet::new();
+
+    fn visit<'a>(
+        todo: &'a Todo,
+        depth: usize,
+        todos: &[&'a Todo],
+        visited: &mut HashSet<usize>,
+        ordered: &mut Vec<(&'a Todo, usize)>,
+    ) {
+        if !visited.insert(todo.get_id()) {
+            return;
+        }
+        ordered.push((todo, depth));
+        for child in todos
+            .iter()
+            .filter(|child| child.get_parent() == Some(todo.get_id()))
+        {
+            visit(child, depth + 1, todos, visited, ordered);
+        }
+    }
+
+    for todo in todos.iter() {
+        let is_root = match todo.get_parent() {
+            Some(parent) => !ids.contains(&parent),
+            None => true,
+        };
+        if is_root {
+            visit(todo, 0, todos, &mut visited, &mut ordered);
+        }
+    }
+    // Parents caught in a cycle have no root, show them anyway.
+    for todo in todos.iter() {
+        visit(todo, 0, todos, &mut visited, &mut ordered);
+    }
+    ordered
+}
+
+// Copy of the todo for listings, indented by depth, with the times and
+// progress of its subtasks rolled up and the todos blocking it.
+pub fn tree_display_todo(todo: &Todo, depth: usize, all_todos: &[Todo]) -> Todo {
+    let mut shown = todo.clone();
+    let mut description = String::new();
+    if depth > 0 {
+        description.push_str(&"  ".repeat(depth - 1));
+        description.push_str("└ ");
+    }
+    description.push_str(&todo.get_description());
+
+    if let Some(r) = rollup(all_todos, todo.get_id()) {
+        description.push_str(&format!(
+            " [{}/{} {}%]",
+            r.children_done,
+            r.children,
+            r.percent_done()
+        ));
+        shown.set_time_estimated(Some(r.estimated));
+        shown.set_time_actual(Some(r.actual));
+    }
+
+    let open = open_dependencies(all_todos, todo);
+    if !todo.done() && !open.is_empty() {
+        description.push_str(&format!(" [blocked by {}]", usize_list_to_string(&open)));
+    }
+    if let Some(r) = todo.get_recurrence() {
+        description.push_str(&format!(" [repeats {}]", recurrence_to_string(r)));
+    }
+    shown.set_description(&description);
+    shown
+}
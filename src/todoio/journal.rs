@@ -0,0 +1,420 @@
+use chrono::{DateTime, Local};
+use std::io::Write;
+
+use super::*;
+use crate::structs::parse::*;
+
+// Number of operations kept when the journal gets compacted.
+const JOURNAL_LIMIT: usize = 100;
+
+// The journal is an append-only text file. Every operation starts with an
+// `@` line, followed by the affected todos before (`-`) and after (`+`) the
+// change. A move to another list adds a `>` line with that list and its ids,
+// followed by the todos added there (`>+`). `U` and `R` lines record that the
+// latest operation was undone or redone.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub time: DateTime<Local>,
+    pub command: String,
+    pub id_before: usize,
+    pub id_after: usize,
+    pub before: Vec<Todo>,
+    pub after: Vec<Todo>,
+    pub moved_to: Option<MovedTo>,
+}
+
+// The other half of t move, so undo and redo change both lists.
+#[derive(Debug, Clone)]
+pub struct MovedTo {
+    pub list: String,
+    pub id_before: usize,
+    pub id_after: usize,
+    pub todos: Vec<Todo>,
+}
+
+impl JournalEntry {
+    pub fn new(
+        command: &str,
+        id_before: usize,
+        id_after: usize,
+        before: Vec<Todo>,
+        after: Vec<Todo>,
+    ) -> JournalEntry {
+        // A note can bring newlines into the command, the @ line has to stay
+        // one line.
+        JournalEntry {
+            time: Local::now(),
+            command: escape_notes(command),
+            id_before,
+            id_after,
+            before,
+            after,
+            moved_to: None,
+        }
+    }
+
+    pub fn changes_nothing(&self) -> bool {
+        self.id_before == self.id_after
+            && self.before.len() == self.after.len()
+            && self
+                .before
+                .iter()
+                .zip(self.after.iter())
+                .all(|(b, a)| b.to_file() == a.to_file())
+    }
+
+    fn to_file(&self) -> String {
+        let mut s = format!(
+            "@\t{}\t{}\t{}\t{}\n",
+            self.time.to_rfc3339(),
+            self.id_before,
+            self.id_after,
+            self.command
+        );
+        for todo in self.before.iter() {
+            s.push_str(&format!("-\t{}", todo.to_file()));
+        }
+        for todo in self.after.iter() {
+            s.push_str(&format!("+\t{}", todo.to_file()));
+        }
+        if let Some(moved_to) = &self.moved_to {
+            s.push_str(&format!(
+                ">\t{}\t{}\t{}\n",
+                moved_to.list, moved_to.id_before, moved_to.id_after
+            ));
+            for todo in moved_to.todos.iter() {
+                s.push_str(&format!(">+\t{}", todo.to_file()));
+            }
+        }
+        s
+    }
+}
+
+// Operations that can be undone, oldest first, and operations that were
+// undone and can be redone, most recently undone last.
+#[derive(Debug, Default)]
+pub struct JournalState {
+    pub done: Vec<JournalEntry>,
+    pub undone: Vec<JournalEntry>,
+    markers: usize,
+}
+
+pub struct Journal {
+    path: PathBuf,
+}
+
+fn journal_error(line_number: usize, msg: &str) -> TodoIOError {
+    TodoIOError::new(&format!("Journal line {}: {}", line_number + 1, msg))
+}
+
+impl Journal {
+    pub fn new(path: &Path) -> Journal {
+        Journal {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn load(&self) -> Result<JournalState, TodoIOError> {
+        let mut state = JournalState::default();
+
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(v) => v,
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => return Ok(state),
+                _ => return Err(TodoIOError::new(&format!("Error reading journal: {}", err))),
+            },
+        };
+
+        for (line_number, line) in content.lines().enumerate() {
+            let (kind, rest) = match line.split_once('\t') {
+                Some(v) => v,
+                None => return Err(journal_error(line_number, "Missing record type")),
+            };
+            match kind {
+                "@" => {
+                    let split: Vec<&str> = rest.splitn(4, '\t').collect();
+                    if split.len() != 4 {
+                        return Err(journal_error(line_number, "Not four fields long"));
+                    }
+                    let time = match DateTime::parse_from_rfc3339(split[0]) {
+                        Ok(v) => v.with_timezone(&Local),
+                        Err(err) => return Err(journal_error(line_number, &err.to_string())),
+                    };
+                    let id_before = parse_usize(split[1])
+                        .map_err(|err| journal_error(line_number, &err.to_string()))?;
+                    let id_after = parse_usize(split[2])
+                        .map_err(|err| journal_error(line_number, &err.to_string()))?;
+                    state.done.push(JournalEntry {
+                        time,
+                        command: split[3].to_string(),
+                        id_before,
+                        id_after,
+                        before: Vec::new(),
+                        after: Vec::new(),
+                        moved_to: None,
+                    });
+                    state.undone.clear();
+                }
+                ">" => {
+                    let split: Vec<&str> = rest.split('\t').collect();
+                    if split.len() != 3 {
+                        return Err(journal_error(line_number, "Not three fields long"));
+                    }
+                    let id_before = parse_usize(split[1])
+                        .map_err(|err| journal_error(line_number, &err.to_string()))?;
+                    let id_after = parse_usize(split[2])
+                        .map_err(|err| journal_error(line_number, &err.to_string()))?;
+                    let entry = match state.done.last_mut() {
+                        Some(v) => v,
+                        None => return Err(journal_error(line_number, "List without operation")),
+                    };
+                    entry.moved_to = Some(MovedTo {
+                        list: split[0].to_string(),
+                        id_before,
+                        id_after,
+                        todos: Vec::new(),
+                    });
+                }
+                "-" | "+" | ">+" => {
+                    let todo: Todo = rest.parse().map_err(|err: ParseTodoError| {
+                        journal_error(line_number, &err.to_string())
+                    })?;
+                    let entry = match state.done.last_mut() {
+                        Some(v) => v,
+                        None => return Err(journal_error(line_number, "Todo without operation")),
+                    };
+                    match (kind, entry.moved_to.as_mut()) {
+                        ("-", _) => entry.before.push(todo),
+                        ("+", _) => entry.after.push(todo),
+                        (_, Some(moved_to)) => moved_to.todos.push(todo),
+                        (_, None) => {
+                            return Err(journal_error(line_number, "Moved Todo without list"))
+                        }
+                    }
+                }
+                "U" => {
+                    state.markers += 1;
+                    if let Some(entry) = state.done.pop() {
+                        state.undone.push(entry);
+                    }
+                }
+                "R" => {
+                    state.markers += 1;
+                    if let Some(entry) = state.undone.pop() {
+                        state.done.push(entry);
+                    }
+                }
+                _ => return Err(journal_error(line_number, "Unknown record type")),
+            }
+        }
+
+        Ok(state)
+    }
+
+    pub fn record(&self, entry: &JournalEntry) -> Result<(), TodoIOError> {
+        self.append(&entry.to_file())?;
+        self.compact_if_needed()
+    }
+
+    pub fn mark_undone(&self) -> Result<(), TodoIOError> {
+        self.append(&format!("U\t{}\n", Local::now().to_rfc3339()))?;
+        self.compact_if_needed()
+    }
+
+    pub fn mark_redone(&self) -> Result<(), TodoIOError> {
+        self.append(&format!("R\t{}\n", Local::now().to_rfc3339()))?;
+        self.compact_if_needed()
+    }
+
+    // The number of operations and markers is kept next to the journal, so
+    // it is only read again when it has to be compacted.
+    fn count_path(&self) -> PathBuf {
+        self.path.with_extension("count")
+    }
+
+    fn write_count(&self, count: usize) -> Result<(), TodoIOError> {
+        std::fs::write(self.count_path(), format!("{}\n", count))
+            .map_err(|err| TodoIOError::new(&format!("Error writing journal: {}", err)))
+    }
+
+    // Called after every record that was appended.
+    fn compact_if_needed(&self) -> Result<(), TodoIOError> {
+        let count = std::fs::read_to_string(self.count_path())
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok());
+        let count = match count {
+            Some(v) => v + 1,
+            // A journal without a count is counted once.
+            None => {
+                let state = self.load()?;
+                state.done.len() + state.undone.len() + state.markers
+            }
+        };
+        if count > 2 * JOURNAL_LIMIT {
+            return self.compact(&self.load()?);
+        }
+        self.write_count(count)
+    }
+
+    // Rewrites the journal keeping the newest JOURNAL_LIMIT operations on
+    // both stacks. Undone entries are written top of stack first, so the
+    // trailing `U` lines move them back onto the redo stack in order.
+    pub fn compact(&self, state: &JournalState) -> Result<(), TodoIOError> {
+        let keep_done = state.done.len().saturating_sub(JOURNAL_LIMIT);
+        let keep_undone = state.undone.len().saturating_sub(JOURNAL_LIMIT);
+
+        let mut content = String::new();
+        for entry in state.done.iter().skip(keep_done) {
+            content.push_str(&entry.to_file());
+        }
+        for entry in state.undone.iter().skip(keep_undone).rev() {
+            content.push_str(&entry.to_file());
+        }
+        for _ in state.undone.iter().skip(keep_undone) {
+            content.push_str(&format!("U\t{}\n", Local::now().to_rfc3339()));
+        }
+
+        if let Err(err) = write_atomic(&self.path, content.as_bytes()) {
+            return Err(TodoIOError::new(&format!("Error writing journal: {}", err)));
+        }
+        let kept_undone = state.undone.len() - keep_undone;
+        self.write_count(state.done.len() - keep_done + 2 * kept_undone)
+    }
+
+    fn append(&self, s: &str) -> Result<(), TodoIOError> {
+        let result = open_file("ac", &self.path).and_then(|mut file| {
+            file.write_all(s.as_bytes())?;
+            file.sync_data()
+        });
+        if let Err(err) = result {
+            return Err(TodoIOError::new(&format!("Error writing journal: {}", err)));
+        }
+        Ok(())
+    }
+}
+
+// Replaces the todos of one side of an entry with the other side, e.g.
+// `after` with `before` when undoing.
+pub fn apply_journal_entry(
+    store: &mut dyn TodoStore,
+    remove: &[Todo],
+    insert: &[Todo],
+    next_id: usize,
+) -> Result<(), TodoIOError> {
+    let removed: Vec<usize> = remove
+        .iter()
+        .map(|todo| todo.get_id())
+        .filter(|id| !insert.iter().any(|i| i.get_id() == *id))
+        .collect();
+    store.store_changes(insert, &removed)?;
+    store.set_next_id(next_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("t-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn multi_line_note_can_be_undone() {
+        let dir = test_dir("journal-note");
+        let mut store = TsvStore::new(&dir.join("todo.txt"), &dir.join("id.txt"));
+        let journal = Journal::new(&dir.join("journal.txt"));
+
+        let mut todo = Todo::new_with_id(store.next_id().unwrap());
+        todo.set_description("Fix login");
+        store.insert(&todo).unwrap();
+
+        let before = todo.clone();
+        todo.add_note("first line\nsecond\tline\r\nC:\\path");
+        store.update(&[todo.clone()]).unwrap();
+        journal
+            .record(&JournalEntry::new(
+                "note 0 first line\nsecond\tline",
+                1,
+                1,
+                vec![before],
+                vec![todo.clone()],
+            ))
+            .unwrap();
+
+        let mut state = journal.load().unwrap();
+        let entry = state.done.pop().unwrap();
+        assert_eq!(entry.command, "note 0 first line\\nsecond\\tline");
+        assert_eq!(entry.after[0].get_notes(), todo.get_notes());
+        assert!(store.load().unwrap()[0].get_notes().contains('\n'));
+
+        apply_journal_entry(&mut store, &entry.after, &entry.before, entry.id_before).unwrap();
+        journal.mark_undone().unwrap();
+        assert_eq!(store.load().unwrap()[0].get_notes(), "");
+        assert_eq!(journal.load().unwrap().undone.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn move_keeps_the_other_list() {
+        let dir = test_dir("journal-move");
+        let journal = Journal::new(&dir.join("journal.txt"));
+
+        let mut todo = Todo::new_with_id(0);
+        todo.set_description("Fix login");
+        let mut moved = todo.clone();
+        moved.set_id(4);
+        let mut entry = JournalEntry::new("move 0 work", 1, 1, vec![todo], vec![]);
+        entry.moved_to = Some(MovedTo {
+            list: String::from("work"),
+            id_before: 4,
+            id_after: 5,
+            todos: vec![moved.clone()],
+        });
+        journal.record(&entry).unwrap();
+        journal
+            .record(&JournalEntry::new("new b", 1, 2, vec![], vec![]))
+            .unwrap();
+
+        let state = journal.load().unwrap();
+        assert_eq!(state.done.len(), 2);
+        assert_eq!(state.done[0].before.len(), 1);
+        assert!(state.done[0].after.is_empty());
+        let moved_to = state.done[0].moved_to.as_ref().unwrap();
+        assert_eq!(moved_to.list, "work");
+        assert_eq!((moved_to.id_before, moved_to.id_after), (4, 5));
+        assert_eq!(moved_to.todos[0].to_file(), moved.to_file());
+        assert!(state.done[1].moved_to.is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compacts_by_the_kept_count() {
+        let dir = test_dir("journal-count");
+        let journal = Journal::new(&dir.join("journal.txt"));
+        let count = || std::fs::read_to_string(dir.join("journal.count")).unwrap();
+        let entry = |i: usize| JournalEntry::new(&format!("new {}", i), i, i + 1, vec![], vec![]);
+
+        for i in 0..2 * JOURNAL_LIMIT {
+            journal.record(&entry(i)).unwrap();
+        }
+        journal.mark_undone().unwrap();
+        // The undone entry is kept with its U line.
+        assert_eq!(count(), format!("{}\n", JOURNAL_LIMIT + 2));
+        let state = journal.load().unwrap();
+        assert_eq!(state.done.len(), JOURNAL_LIMIT);
+        assert_eq!(state.undone.len(), 1);
+
+        // Without the count the journal is counted again.
+        std::fs::remove_file(dir.join("journal.count")).unwrap();
+        journal.record(&entry(0)).unwrap();
+        assert_eq!(count(), format!("{}\n", JOURNAL_LIMIT + 2));
+        assert_eq!(journal.load().unwrap().done.len(), JOURNAL_LIMIT + 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::io::ErrorKind;
+
+use super::*;
+
+// The list that lives directly in the todo directory, where the todos were
+// stored before there were lists. Every other list has its own directory
+// below lists/ with its own todo file, id counter and journal.
+pub const DEFAULT_LIST: &str = "default";
+
+pub fn check_list_name(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "Invalid list name {}, use letters, digits, - and _",
+            name
+        ));
+    }
+    Ok(())
+}
+
+pub struct Lists {
+    todo_dir: PathBuf,
+    // Replaces the todo file of the default list (--file), its id counter
+    // and journal are kept next to it.
+    default_file: Option<PathBuf>,
+}
+
+impl Lists {
+    pub fn new(todo_dir: &Path, default_file: Option<&Path>) -> Lists {
+        Lists {
+            todo_dir: todo_dir.to_path_buf(),
+            default_file: default_file.map(|v| v.to_path_buf()),
+        }
+    }
+
+    fn lists_dir(&self) -> PathBuf {
+        self.todo_dir.join("lists")
+    }
+
+    fn file(&self, name: &str) -> Option<&Path> {
+        match &self.default_file {
+            Some(v) if name == DEFAULT_LIST => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn dir(&self, name: &str) -> PathBuf {
+        match self.file(name).map(|v| v.parent()) {
+            Some(Some(v)) if !v.as_os_str().is_empty() => v.to_path_buf(),
+            Some(_) => PathBuf::from("."),
+            None if name == DEFAULT_LIST => self.todo_dir.clone(),
+            None => self.lists_dir().join(name),
+        }
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        name == DEFAULT_LIST || (check_list_name(name).is_ok() && self.dir(name).is_dir())
+    }
+
+    // All lists by name, the default list first.
+    pub fn names(&self) -> Result<Vec<String>, TodoIOError> {
+        let mut names = Vec::new();
+        let entries = match fs::read_dir(self.lists_dir()) {
+            Ok(v) => v,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Ok(vec![DEFAULT_LIST.to_string()])
+            }
+            Err(err) => {
+                return Err(TodoIOError::new(&format!(
+                    "Error reading lists directory: {}",
+                    err
+                )))
+            }
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name != DEFAULT_LIST && self.exists(&name) {
+                names.push(name);
+            }
+        }
+        names.sort();
+        names.insert(0, DEFAULT_LIST.to_string());
+        Ok(names)
+    }
+
+    pub fn create(&self, name: &str) -> Result<(), TodoIOError> {
+        if let Err(err) = check_list_name(name) {
+            return Err(TodoIOError::new(&err));
+        }
+        if self.exists(name) {
+            return Err(TodoIOError::new(&format!("List {} already exists", name)));
+        }
+        if let Err(err) = fs::create_dir_all(self.dir(name)) {
+            return Err(TodoIOError::new(&format!(
+                "Error creating list {}: {}",
+                name, err
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn open(&self, name: &str, config: &Config) -> Result<Box<dyn TodoStore>, TodoIOError> {
+        open_store(config, &self.dir(name), self.file(name))
+    }
+
+    pub fn journal(&self, name: &str) -> Journal {
+        Journal::new(&self.dir(name).join("journal.txt"))
+    }
+}
@@ -0,0 +1,314 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::config::*;
+use super::structs::*;
+
+pub mod journal;
+pub mod lists;
+pub mod sqlite;
+pub mod tsv;
+pub use journal::*;
+pub use lists::*;
+use sqlite::*;
+use tsv::*;
+
+pub trait TodoStore {
+    fn load(&mut self) -> Result<Vec<Todo>, TodoIOError>;
+    fn store_all(&mut self, todos: &[Todo]) -> Result<(), TodoIOError>;
+    // Returns the next free id and reserves it.
+    fn next_id(&mut self) -> Result<usize, TodoIOError>;
+    // Returns the next free id without reserving it.
+    fn peek_id(&mut self) -> Result<usize, TodoIOError>;
+    fn set_next_id(&mut self, id: usize) -> Result<(), TodoIOError>;
+
+    fn get(&mut self, id: usize) -> Result<Option<Todo>, TodoIOError> {
+        Ok(self.load()?.into_iter().find(|todo| todo.get_id() == id))
+    }
+    fn insert(&mut self, todo: &Todo) -> Result<(), TodoIOError> {
+        let mut todos = self.load()?;
+        todos.push(todo.clone());
+        self.store_all(&todos)
+    }
+    fn update(&mut self, updated: &[Todo]) -> Result<(), TodoIOError> {
+        let mut todos = self.load()?;
+        for todo in todos.iter_mut() {
+            if let Some(v) = updated.iter().find(|u| u.get_id() == todo.get_id()) {
+                *todo = v.clone();
+            }
+        }
+        self.store_all(&todos)
+    }
+    fn delete(&mut self, ids: &[usize]) -> Result<(), TodoIOError> {
+        let mut todos = self.load()?;
+        todos.retain(|todo| !ids.contains(&todo.get_id()));
+        self.store_all(&todos)
+    }
+    // Writes the changed todos, adding the ones that are new, and removes
+    // the ones with the given ids, all in one go.
+    fn store_changes(&mut self, changed: &[Todo], removed: &[usize]) -> Result<(), TodoIOError> {
+        let mut todos = self.load()?;
+        todos.retain(|todo| !removed.contains(&todo.get_id()));
+        for todo in changed.iter() {
+            match todos.iter().position(|t| t.get_id() == todo.get_id()) {
+                Some(index) => todos[index] = todo.clone(),
+                None => todos.push(todo.clone()),
+            }
+        }
+        todos.sort_by_key(|todo| todo.get_id());
+        self.store_all(&todos)
+    }
+}
+
+// Opens the todos in todo_dir. `file` replaces todo.txt, or todo.db with the
+// sqlite backend.
+pub fn open_store(
+    config: &Config,
+    todo_dir: &Path,
+    file: Option<&Path>,
+) -> Result<Box<dyn TodoStore>, TodoIOError> {
+    let todo_file_path = match (config.backend, file) {
+        (Backend::Tsv, Some(v)) => v.to_path_buf(),
+        _ => todo_dir.join("todo.txt"),
+    };
+    let id_file_path = todo_dir.join("id.txt");
+    let mut tsv_store = TsvStore::new(&todo_file_path, &id_file_path);
+
+    match config.backend {
+        Backend::Tsv => Ok(Box::new(tsv_store)),
+        Backend::Sqlite => {
+            let db_path = match file {
+                Some(v) => v.to_path_buf(),
+                None => todo_dir.join("todo.db"),
+            };
+            let is_new = !db_path.exists();
+
+            // Carry the existing text file over when switching backends. It
+            // is read before the database is created, and a database that
+            // could not be filled is removed again. Otherwise the next run
+            // would take it as migrated and show no todos.
+            let carried = if is_new && todo_file_path.exists() {
+                Some((tsv_store.load()?, tsv_store.peek_id()?))
+            } else {
+                None
+            };
+            let opened = SqliteStore::open(&db_path).and_then(|mut sqlite_store| {
+                if let Some((todos, next_id)) = &carried {
+                    sqlite_store.store_all_with_next_id(todos, *next_id)?;
+                }
+                Ok(sqlite_store)
+            });
+            match opened {
+                Ok(v) => Ok(Box::new(v)),
+                Err(err) => {
+                    if is_new {
+                        let _ = std::fs::remove_file(&db_path);
+                    }
+                    Err(err)
+                }
+            }
+        }
+    }
+}
+
+fn open_file(open_option_string: &str, path: &Path) -> Result<File, std::io::Error> {
+    let mut oo = OpenOptions::new();
+
+    if open_option_string.contains('r') {
+        oo.read(true);
+    }
+    if open_option_string.contains('w') {
+        oo.write(true);
+    }
+    if open_option_string.contains('a') {
+        oo.append(true);
+    }
+    if open_option_string.contains('c') {
+        oo.create(true);
+    }
+    if open_option_string.contains('t') {
+        oo.truncate(true);
+    }
+
+    oo.open(path)
+}
+
+// Writes the whole content to a temporary file next to `path`, syncs it and
+// renames it over the original, so readers either see the old or the new file.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let tmp_file = open_file("wct", &tmp_path)?;
+    let mut writer = BufWriter::new(tmp_file);
+    if let Err(err) = writer.write_all(content) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+    let tmp_file = match writer.into_inner() {
+        Ok(v) => v,
+        Err(err) => {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(err.into_error());
+        }
+    };
+    if let Err(err) = tmp_file.sync_all() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+    drop(tmp_file);
+
+    std::fs::rename(&tmp_path, path)?;
+    sync_parent_dir(path);
+
+    Ok(())
+}
+
+// Makes the rename itself durable. Not every platform allows opening a
+// directory, so failures are ignored.
+fn sync_parent_dir(path: &Path) {
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+// Exclusive advisory lock on the todo directory. It is released when the
+// value is dropped, so keep it alive for the whole load-modify-store cycle.
+pub struct TodoLock {
+    file: File,
+}
+
+impl Drop for TodoLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+pub fn lock_todo_files(lock_file_path: &Path) -> Result<TodoLock, TodoIOError> {
+    let file = match open_file("wc", lock_file_path) {
+        Ok(v) => v,
+        Err(err) => {
+            return Err(TodoIOError::new(&format!(
+                "Error opening lock file {}: {}",
+                lock_file_path.display(),
+                err
+            )))
+        }
+    };
+
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(TodoLock { file }),
+            Err(TryLockError::WouldBlock) => {
+                if started.elapsed() >= LOCK_TIMEOUT {
+                    return Err(TodoIOError::new(&format!(
+                        "Could not lock {} within {} seconds, is another t running?",
+                        lock_file_path.display(),
+                        LOCK_TIMEOUT.as_secs()
+                    )));
+                }
+                thread::sleep(LOCK_RETRY_INTERVAL);
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(TodoIOError::new(&format!(
+                    "Error locking {}: {}",
+                    lock_file_path.display(),
+                    err
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("t-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn sqlite_config() -> Config {
+        let mut config = Config::default();
+        config.backend = Backend::Sqlite;
+        config
+    }
+
+    #[test]
+    fn switching_to_sqlite_carries_the_todos_over() {
+        let dir = test_dir("migrate");
+        let mut tsv_store = TsvStore::new(&dir.join("todo.txt"), &dir.join("id.txt"));
+        let mut todo = Todo::new_with_id(tsv_store.next_id().unwrap());
+        todo.set_description("Fix login");
+        tsv_store.insert(&todo).unwrap();
+
+        let mut store = open_store(&sqlite_config(), &dir, None).unwrap();
+        assert_eq!(store.load().unwrap()[0].to_file(), todo.to_file());
+        assert_eq!(store.peek_id().unwrap(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_migration_leaves_no_database() {
+        let dir = test_dir("migrate-fail");
+        std::fs::write(dir.join("todo.txt"), "not a todo\n").unwrap();
+
+        assert!(open_store(&sqlite_config(), &dir, None).is_err());
+        assert!(!dir.join("todo.db").exists());
+        // Fixing the file lets the next run migrate.
+        std::fs::write(dir.join("todo.txt"), Todo::new_with_id(3).to_file()).unwrap();
+        let mut store = open_store(&sqlite_config(), &dir, None).unwrap();
+        assert_eq!(store.load().unwrap().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn store_changes_only_touches_the_given_todos() {
+        for config in [Config::default(), sqlite_config()] {
+            let dir = test_dir(&format!("changes-{:?}", config.backend));
+            let mut store = open_store(&config, &dir, None).unwrap();
+            let todos: Vec<Todo> = (0..3)
+                .map(|id| {
+                    let mut todo = Todo::new_with_id(id);
+                    todo.set_description(&format!("Todo {}", id));
+                    todo
+                })
+                .collect();
+            store.store_all(&todos).unwrap();
+
+            let mut changed = todos[1].clone();
+            changed.set_description("Changed");
+            let added = Todo::new_with_id(5);
+            store
+                .store_changes(&[added.clone(), changed.clone()], &[0])
+                .unwrap();
+
+            let files: Vec<String> = store.load().unwrap().iter().map(|t| t.to_file()).collect();
+            assert_eq!(
+                files,
+                vec![changed.to_file(), todos[2].to_file(), added.to_file()]
+            );
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
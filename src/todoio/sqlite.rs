@@ -0,0 +1,309 @@
+use rusqlite::{params, Connection, Row, Transaction, TransactionBehavior};
+use std::time::Duration;
+
+use super::*;
+use crate::structs::parse::*;
+
+// Every entry upgrades the schema by one version, tracked in user_version.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE todos (
+        id INTEGER PRIMARY KEY,
+        priority INTEGER NOT NULL,
+        description TEXT NOT NULL,
+        projects TEXT NOT NULL,
+        categories TEXT NOT NULL,
+        time_estimated INTEGER,
+        time_actual INTEGER,
+        status TEXT NOT NULL,
+        color TEXT NOT NULL
+    );
+    CREATE INDEX todos_status ON todos(status);
+    CREATE INDEX todos_priority ON todos(priority);
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    "#,
+    r#"
+    ALTER TABLE todos ADD COLUMN due TEXT;
+    ALTER TABLE todos ADD COLUMN scheduled TEXT;
+    CREATE INDEX todos_due ON todos(due);
+    "#,
+    r#"
+    ALTER TABLE todos ADD COLUMN sessions TEXT NOT NULL DEFAULT '';
+    "#,
+    r#"
+    ALTER TABLE todos ADD COLUMN parent INTEGER;
+    CREATE INDEX todos_parent ON todos(parent);
+    "#,
+    r#"
+    ALTER TABLE todos ADD COLUMN depends TEXT NOT NULL DEFAULT '';
+    "#,
+    r#"
+    ALTER TABLE todos ADD COLUMN recurrence TEXT NOT NULL DEFAULT '';
+    "#,
+    r#"
+    ALTER TABLE todos ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+    "#,
+];
+
+const TODO_COLUMNS: &str = "id, priority, description, projects, categories, \
+    time_estimated, time_actual, status, color, due, scheduled, sessions, parent, depends, \
+    recurrence, notes";
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+fn sql_error(err: rusqlite::Error) -> TodoIOError {
+    TodoIOError::new(&format!("SQLite error: {}", err))
+}
+
+impl SqliteStore {
+    pub fn open(db_path: &Path) -> Result<SqliteStore, TodoIOError> {
+        let conn = Connection::open(db_path).map_err(sql_error)?;
+        let mut store = SqliteStore { conn };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    fn migrate(&mut self) -> Result<(), TodoIOError> {
+        let version: i64 = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(sql_error)?;
+
+        let tx = self.transaction()?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            tx.execute_batch(migration).map_err(sql_error)?;
+            tx.pragma_update(None, "user_version", i as i64 + 1)
+                .map_err(sql_error)?;
+        }
+        tx.commit().map_err(sql_error)
+    }
+
+    // Replaces all todos and the next id at once, for carrying a todo file
+    // over.
+    pub fn store_all_with_next_id(
+        &mut self,
+        todos: &[Todo],
+        next_id: usize,
+    ) -> Result<(), TodoIOError> {
+        let tx = self.transaction()?;
+        tx.execute("DELETE FROM todos", []).map_err(sql_error)?;
+        for todo in todos.iter() {
+            insert_todo(&tx, todo)?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('next_id', ?1)",
+            params![next_id as i64],
+        )
+        .map_err(sql_error)?;
+        tx.commit().map_err(sql_error)
+    }
+
+    fn transaction(&mut self) -> Result<Transaction<'_>, TodoIOError> {
+        self.conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sql_error)
+    }
+}
+
+fn duration_to_sql(d: Option<Duration>) -> Option<i64> {
+    d.map(|v| v.as_secs() as i64)
+}
+
+fn row_to_todo(row: &Row) -> rusqlite::Result<Result<Todo, ParseTodoError>> {
+    let id: i64 = row.get(0)?;
+    let priority: i64 = row.get(1)?;
+    let description: String = row.get(2)?;
+    let projects: String = row.get(3)?;
+    let categories: String = row.get(4)?;
+    let time_estimated: Option<i64> = row.get(5)?;
+    let time_actual: Option<i64> = row.get(6)?;
+    let status: String = row.get(7)?;
+    let color: String = row.get(8)?;
+    let due: Option<String> = row.get(9)?;
+    let scheduled: Option<String> = row.get(10)?;
+    let sessions: String = row.get(11)?;
+    let parent: Option<i64> = row.get(12)?;
+    let depends: String = row.get(13)?;
+    let recurrence: String = row.get(14)?;
+    let notes: String = row.get(15)?;
+
+    let mut todo = Todo::new_with_id(id as usize);
+    todo.set_priority(priority as isize);
+    todo.set_description(&description);
+    todo.set_projects(&projects);
+    todo.set_categories(&categories);
+    todo.set_time_estimated(time_estimated.map(|v| Duration::from_secs(v as u64)));
+    todo.set_time_actual(time_actual.map(|v| Duration::from_secs(v as u64)));
+    if let Err(err) = todo.set_status_from_string(&status) {
+        return Ok(Err(err));
+    }
+    todo.set_color_from_string(&color);
+    todo.set_parent(parent.map(|v| v as usize));
+    todo.set_notes(&notes);
+    match parse_usize_list(&depends) {
+        Ok(v) => todo.set_depends(v),
+        Err(err) => return Ok(Err(err)),
+    }
+    match parse_recurrence(&recurrence) {
+        Ok(v) => todo.set_recurrence(v),
+        Err(err) => return Ok(Err(err)),
+    }
+    match parse_date_result(&due.unwrap_or_default()) {
+        Ok(v) => todo.set_due(v),
+        Err(err) => return Ok(Err(err)),
+    }
+    match parse_date_result(&scheduled.unwrap_or_default()) {
+        Ok(v) => todo.set_scheduled(v),
+        Err(err) => return Ok(Err(err)),
+    }
+    match parse_sessions(&sessions) {
+        Ok(v) => todo.set_sessions(v),
+        Err(err) => return Ok(Err(err)),
+    }
+
+    Ok(Ok(todo))
+}
+
+fn insert_todo(tx: &Transaction, todo: &Todo) -> Result<(), TodoIOError> {
+    tx.execute(
+        &format!(
+            "INSERT OR REPLACE INTO todos ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            TODO_COLUMNS
+        ),
+        params![
+            todo.get_id() as i64,
+            todo.get_priority() as i64,
+            todo.get_description(),
+            todo.get_projects(),
+            todo.get_categories(),
+            duration_to_sql(todo.get_time_estimated()),
+            duration_to_sql(todo.get_time_actual()),
+            todo.get_status().to_string(),
+            color_to_string(todo.get_color()),
+            todo.get_due().map(date_to_string),
+            todo.get_scheduled().map(date_to_string),
+            sessions_to_string(todo.get_sessions()),
+            todo.get_parent().map(|v| v as i64),
+            usize_list_to_string(todo.get_depends()),
+            todo.get_recurrence().map(recurrence_to_string).unwrap_or_default(),
+            todo.get_notes(),
+        ],
+    )
+    .map_err(sql_error)?;
+    Ok(())
+}
+
+fn collect_todos(
+    rows: impl Iterator<Item = rusqlite::Result<Result<Todo, ParseTodoError>>>,
+) -> Result<Vec<Todo>, TodoIOError> {
+    let mut todos = Vec::new();
+    for row in rows {
+        match row.map_err(sql_error)? {
+            Ok(v) => todos.push(v),
+            Err(err) => return Err(TodoIOError::new(&format!("Error Parsing Todo: {}", err))),
+        }
+    }
+    Ok(todos)
+}
+
+fn query_next_id(conn: &Connection) -> Result<i64, TodoIOError> {
+    match conn.query_row("SELECT value FROM meta WHERE key = 'next_id'", [], |row| {
+        row.get(0)
+    }) {
+        Ok(v) => Ok(v),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(0),
+        Err(err) => Err(sql_error(err)),
+    }
+}
+
+impl TodoStore for SqliteStore {
+    fn load(&mut self) -> Result<Vec<Todo>, TodoIOError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {} FROM todos ORDER BY id", TODO_COLUMNS))
+            .map_err(sql_error)?;
+        let rows = stmt.query_map([], row_to_todo).map_err(sql_error)?;
+        collect_todos(rows)
+    }
+    fn store_all(&mut self, todos: &[Todo]) -> Result<(), TodoIOError> {
+        let tx = self.transaction()?;
+        tx.execute("DELETE FROM todos", []).map_err(sql_error)?;
+        for todo in todos.iter() {
+            insert_todo(&tx, todo)?;
+        }
+        tx.commit().map_err(sql_error)
+    }
+    fn next_id(&mut self) -> Result<usize, TodoIOError> {
+        let tx = self.transaction()?;
+        let current: Option<i64> =
+            match tx.query_row("SELECT value FROM meta WHERE key = 'next_id'", [], |row| {
+                row.get(0)
+            }) {
+                Ok(v) => Some(v),
+                Err(rusqlite::Error::QueryReturnedNoRows) => None,
+                Err(err) => return Err(sql_error(err)),
+            };
+        let current = current.unwrap_or(0);
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('next_id', ?1)",
+            params![current + 1],
+        )
+        .map_err(sql_error)?;
+        tx.commit().map_err(sql_error)?;
+        Ok(current as usize)
+    }
+    fn peek_id(&mut self) -> Result<usize, TodoIOError> {
+        Ok(query_next_id(&self.conn)? as usize)
+    }
+    fn set_next_id(&mut self, id: usize) -> Result<(), TodoIOError> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('next_id', ?1)",
+                params![id as i64],
+            )
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    fn get(&mut self, id: usize) -> Result<Option<Todo>, TodoIOError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {} FROM todos WHERE id = ?1", TODO_COLUMNS))
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map(params![id as i64], row_to_todo)
+            .map_err(sql_error)?;
+        Ok(collect_todos(rows)?.into_iter().next())
+    }
+    fn insert(&mut self, todo: &Todo) -> Result<(), TodoIOError> {
+        let tx = self.transaction()?;
+        insert_todo(&tx, todo)?;
+        tx.commit().map_err(sql_error)
+    }
+    fn update(&mut self, updated: &[Todo]) -> Result<(), TodoIOError> {
+        let tx = self.transaction()?;
+        for todo in updated.iter() {
+            insert_todo(&tx, todo)?;
+        }
+        tx.commit().map_err(sql_error)
+    }
+    fn delete(&mut self, ids: &[usize]) -> Result<(), TodoIOError> {
+        self.store_changes(&[], ids)
+    }
+    fn store_changes(&mut self, changed: &[Todo], removed: &[usize]) -> Result<(), TodoIOError> {
+        let tx = self.transaction()?;
+        for id in removed.iter() {
+            tx.execute("DELETE FROM todos WHERE id = ?1", params![*id as i64])
+                .map_err(sql_error)?;
+        }
+        for todo in changed.iter() {
+            insert_todo(&tx, todo)?;
+        }
+        tx.commit().map_err(sql_error)
+    }
+}
@@ -0,0 +1,124 @@
+use std::io::{BufRead, BufReader, Read};
+
+use super::*;
+
+pub struct TsvStore {
+    todo_file_path: PathBuf,
+    id_file_path: PathBuf,
+}
+
+impl TsvStore {
+    pub fn new(todo_file_path: &Path, id_file_path: &Path) -> TsvStore {
+        TsvStore {
+            todo_file_path: todo_file_path.to_path_buf(),
+            id_file_path: id_file_path.to_path_buf(),
+        }
+    }
+}
+
+impl TodoStore for TsvStore {
+    fn load(&mut self) -> Result<Vec<Todo>, TodoIOError> {
+        get_all_todos(&self.todo_file_path)
+    }
+    fn store_all(&mut self, todos: &[Todo]) -> Result<(), TodoIOError> {
+        store_all_todos(todos, &self.todo_file_path)
+    }
+    fn next_id(&mut self) -> Result<usize, TodoIOError> {
+        match get_current_id(&self.id_file_path) {
+            Ok(v) => Ok(v),
+            Err(err) => Err(TodoIOError::new(&format!("Error getting new ID: {}", err))),
+        }
+    }
+    fn peek_id(&mut self) -> Result<usize, TodoIOError> {
+        match read_current_id(&self.id_file_path) {
+            Ok(v) => Ok(v),
+            Err(err) => Err(TodoIOError::new(&format!("Error reading ID: {}", err))),
+        }
+    }
+    fn set_next_id(&mut self, id: usize) -> Result<(), TodoIOError> {
+        match set_current_id(id, &self.id_file_path) {
+            Ok(v) => Ok(v),
+            Err(err) => Err(TodoIOError::new(&format!("Error resetting ID: {}", err))),
+        }
+    }
+}
+
+pub fn read_current_id(id_file_path: &Path) -> Result<usize, std::io::Error> {
+    let mut buffer = String::new();
+    match open_file("r", id_file_path) {
+        Ok(mut id_file) => {
+            id_file.read_to_string(&mut buffer)?;
+        }
+        Err(err) => {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(err);
+            }
+        }
+    }
+
+    let buffer = buffer.trim();
+
+    Ok(buffer.parse::<usize>().unwrap_or(0))
+}
+
+pub fn get_current_id(id_file_path: &Path) -> Result<usize, std::io::Error> {
+    let current_id = read_current_id(id_file_path)?;
+
+    set_current_id(current_id + 1, id_file_path)?;
+
+    Ok(current_id)
+}
+
+pub fn set_current_id(new_id: usize, id_file_path: &Path) -> Result<(), std::io::Error> {
+    write_atomic(id_file_path, new_id.to_string().as_bytes())
+}
+
+pub fn get_all_todos(todo_path: &Path) -> Result<Vec<Todo>, TodoIOError> {
+    let mut todos = Vec::new();
+
+    let todo_file = match open_file("r", todo_path) {
+        Ok(v) => v,
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            _ => {
+                return Err(TodoIOError::new(&format!(
+                    "Error opening todo file: {}",
+                    err
+                )))
+            }
+        },
+    };
+    let reader = BufReader::new(todo_file);
+    for line in reader.lines() {
+        let line = match line {
+            Ok(v) => v,
+            Err(err) => {
+                return Err(TodoIOError::new(&format!(
+                    "Error reading todo file: {}",
+                    err
+                )))
+            }
+        };
+        let todo: Todo = match line.parse() {
+            Ok(v) => v,
+            Err(err) => return Err(TodoIOError::new(&format!("Error Parsing Todo: {}", err))),
+        };
+        todos.push(todo);
+    }
+    Ok(todos)
+}
+
+pub fn store_all_todos(todos: &[Todo], todo_path: &Path) -> Result<(), TodoIOError> {
+    let mut content = String::new();
+    for todo in todos.iter() {
+        content.push_str(&todo.to_file());
+    }
+
+    if let Err(err) = write_atomic(todo_path, content.as_bytes()) {
+        return Err(TodoIOError::new(&format!(
+            "Error writing todo file: {}",
+            err
+        )));
+    }
+    Ok(())
+}
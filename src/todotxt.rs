@@ -0,0 +1,369 @@
+use chrono::NaiveDateTime;
+use std::time::Duration;
+
+use super::structs::parse::*;
+use super::structs::*;
+
+// Conversion from and to the todo.txt format, one todo per line:
+//
+//   x (A) description +project @category key:value
+//
+// Priorities 9 to 1 are the letters A to I, other priorities are written as
+// prio:N. Projects and categories are split on whitespace into +project and
+// @category. Everything else goes into key:value extensions, see README.
+// Description words that would be read as one of those get a backslash in
+// front.
+
+const PRIORITY_LETTERS: u8 = 9;
+
+// The extensions written by export. Any other key:value stays part of the
+// description, so links like https://example.com survive an import.
+const KEYS: &[&str] = &[
+    "id", "prio", "pri", "est", "act", "status", "color", "due", "t", "rec", "parent", "dep",
+    "sessions", "note",
+];
+
+pub fn priority_to_letter(priority: isize) -> Option<char> {
+    if priority >= 1 && priority <= PRIORITY_LETTERS as isize {
+        Some((b'A' + PRIORITY_LETTERS - priority as u8) as char)
+    } else {
+        None
+    }
+}
+
+// A is 9, I is 1, letters after it continue downwards: J is 0, Z is -16.
+pub fn letter_to_priority(letter: char) -> Option<isize> {
+    if letter.is_ascii_uppercase() {
+        Some(PRIORITY_LETTERS as isize - (letter as u8 - b'A') as isize)
+    } else {
+        None
+    }
+}
+
+// A single letter like the A in (A) or pri:A.
+fn parse_letter(s: &str) -> Option<isize> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => letter_to_priority(c),
+        _ => None,
+    }
+}
+
+fn date_to_todotxt(d: NaiveDateTime) -> String {
+    if is_date_only(d) {
+        d.format("%Y-%m-%d").to_string()
+    } else {
+        d.format("%Y-%m-%dT%H:%M").to_string()
+    }
+}
+
+// rec:2w repeats two weeks after the todo was done, rec:+2w keeps the
+// schedule. Weekdays are written as rec:+mon,thu.
+fn recurrence_to_todotxt(r: &Recurrence) -> String {
+    let prefix = if r.after_completion { "" } else { "+" };
+    let rule = match &r.interval {
+        RecurrenceInterval::Days(n) => format!("{}d", n),
+        RecurrenceInterval::Weeks(n) => format!("{}w", n),
+        RecurrenceInterval::Months(n) => format!("{}m", n),
+        RecurrenceInterval::Years(n) => format!("{}y", n),
+        RecurrenceInterval::Weekdays(days) => days
+            .iter()
+            .map(|v| v.to_string().to_lowercase())
+            .collect::<Vec<String>>()
+            .join(","),
+    };
+    format!("{}{}", prefix, rule)
+}
+
+fn todotxt_to_recurrence(s: &str) -> Result<Option<Recurrence>, String> {
+    let (prefix, rule) = match s.strip_prefix('+') {
+        Some(v) => ("every", v),
+        None => ("after", s),
+    };
+    let unit = match rule.chars().last() {
+        Some('d') => Some("days"),
+        Some('w') => Some("weeks"),
+        Some('m') => Some("months"),
+        Some('y') => Some("years"),
+        _ => None,
+    };
+    let count = &rule[..rule.len().saturating_sub(1)];
+    let rule = match unit {
+        Some(unit) if !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()) => {
+            format!("{} {} {}", prefix, count, unit)
+        }
+        _ => format!("{} {}", prefix, rule),
+    };
+    parse_recurrence(&rule).map_err(|err| err.to_string())
+}
+
+// Whether import reads the word as a key:value extension.
+fn is_extension(word: &str) -> bool {
+    matches!(word.split_once(':'), Some((k, v)) if KEYS.contains(&k) && !v.is_empty())
+}
+
+// A word of the description as written by export. Words import would take
+// for a project, category or extension and words starting with a backslash
+// get one more in front, a lone backslash is an empty word so runs of
+// spaces are kept.
+fn escape_description_word(word: &str) -> String {
+    if word.starts_with(['+', '@', '\\']) || word.is_empty() || is_extension(word) {
+        format!("\\{}", word)
+    } else {
+        word.to_string()
+    }
+}
+
+fn duration_to_todotxt(d: Option<Duration>) -> Option<String> {
+    match d {
+        Some(v) if v.as_secs() > 0 => Some(duration_to_human_string(v)),
+        _ => None,
+    }
+}
+
+pub fn todo_to_todotxt(todo: &Todo) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let letter = priority_to_letter(todo.get_priority());
+
+    if todo.done() {
+        parts.push(String::from("x"));
+    } else if let Some(v) = letter {
+        parts.push(format!("({})", v));
+    }
+
+    // A description starting like a done mark, a priority or a date would
+    // be read as one, the id in front keeps it in the description.
+    let description = todo.get_description();
+    let first = description.split_whitespace().next().unwrap_or("");
+    let id_first = first == "x" || is_date(first) || is_priority(first);
+    if id_first {
+        parts.push(format!("id:{}", todo.get_id()));
+    }
+
+    if !description.is_empty() {
+        parts.extend(description.split(' ').map(escape_description_word));
+    }
+    parts.extend(
+        todo.get_projects()
+            .split_whitespace()
+            .map(|v| format!("+{}", v)),
+    );
+    parts.extend(
+        todo.get_categories()
+            .split_whitespace()
+            .map(|v| format!("@{}", v)),
+    );
+
+    let mut extensions = Vec::new();
+    if !id_first {
+        extensions.push((String::from("id"), todo.get_id().to_string()));
+    }
+    if todo.get_priority() != 0 && (todo.done() || letter.is_none()) {
+        extensions.push((String::from("prio"), todo.get_priority().to_string()));
+    }
+    if let Some(v) = duration_to_todotxt(todo.get_time_estimated()) {
+        extensions.push((String::from("est"), v));
+    }
+    if let Some(v) = duration_to_todotxt(todo.get_time_actual()) {
+        extensions.push((String::from("act"), v));
+    }
+    if !todo.done() && todo.get_status() != TodoStatus::New {
+        extensions.push((String::from("status"), todo.get_status().to_string()));
+    }
+    if todo.get_color() != ansi_term::Color::White {
+        extensions.push((String::from("color"), color_to_string(todo.get_color())));
+    }
+    if let Some(v) = todo.get_due() {
+        extensions.push((String::from("due"), date_to_todotxt(v)));
+    }
+    if let Some(v) = todo.get_scheduled() {
+        extensions.push((String::from("t"), date_to_todotxt(v)));
+    }
+    if let Some(v) = todo.get_recurrence() {
+        extensions.push((String::from("rec"), recurrence_to_todotxt(v)));
+    }
+    if let Some(v) = todo.get_parent() {
+        extensions.push((String::from("parent"), v.to_string()));
+    }
+    if !todo.get_depends().is_empty() {
+        extensions.push((
+            String::from("dep"),
+            usize_list_to_string(todo.get_depends()),
+        ));
+    }
+    if !todo.get_sessions().is_empty() {
+        extensions.push((
+            String::from("sessions"),
+            sessions_to_string(todo.get_sessions()),
+        ));
+    }
+    let notes = todo.get_notes();
+    if !notes.is_empty() {
+        extensions.push((
+            String::from("note"),
+            escape_notes(&notes).replace(' ', "\\s"),
+        ));
+    }
+
+    parts.extend(extensions.iter().map(|(k, v)| format!("{}:{}", k, v)));
+    parts.join(" ")
+}
+
+fn is_date(s: &str) -> bool {
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()
+}
+
+fn is_priority(s: &str) -> bool {
+    s.strip_prefix('(')
+        .and_then(|v| v.strip_suffix(')'))
+        .and_then(parse_letter)
+        .is_some()
+}
+
+// Reads one line of a todo.txt file. The id is only set when the line has
+// an id: extension, creation and completion dates are skipped.
+pub fn parse_todotxt_line(line: &str, work_day: Duration) -> Result<(Todo, Option<usize>), String> {
+    let mut words: Vec<&str> = line.split_whitespace().collect();
+    let mut todo = Todo::new();
+    let mut id = None;
+    if words.is_empty() {
+        return Err(String::from("empty line"));
+    }
+
+    if words.first() == Some(&"x") {
+        words.remove(0);
+        todo.set_status(TodoStatus::Done).unwrap();
+        // Completion date, then creation date.
+        for _ in 0..2 {
+            if words.first().map(|v| is_date(v)) == Some(true) {
+                words.remove(0);
+            }
+        }
+    }
+    if let Some(word) = words.first() {
+        if is_priority(word) {
+            todo.set_priority(parse_letter(&word[1..2]).unwrap());
+            words.remove(0);
+        }
+    }
+    // Creation date.
+    if !todo.done() && words.first().map(|v| is_date(v)) == Some(true) {
+        words.remove(0);
+    }
+
+    let mut description = Vec::new();
+    for word in words {
+        if let Some(v) = word.strip_prefix('\\') {
+            description.push(v);
+            continue;
+        }
+        // +project and @category work like in t new.
+        if word.starts_with(['+', '@'])
+            && parse_quick_add_word(&mut todo, word, work_day).map_err(|e| e.to_string())?
+        {
+            continue;
+        }
+        if !is_extension(word) {
+            description.push(word);
+            continue;
+        }
+        let (key, value) = word.split_once(':').unwrap();
+
+        let error = |err: &dyn std::fmt::Display| format!("{}: {}", word, err);
+        match key {
+            "id" => id = Some(parse_usize(value).map_err(|e| error(&e))?),
+            "prio" => todo
+                .set_priority_from_string(value)
+                .map(|_| ())
+                .map_err(|e| error(&e))?,
+            "pri" => match parse_letter(value) {
+                Some(v) => todo.set_priority(v),
+                None => return Err(error(&"expected a letter from A to Z")),
+            },
+            "est" => todo
+                .set_time_estimated_from_string(value, work_day)
+                .map(|_| ())
+                .map_err(|e| error(&e))?,
+            "act" => todo
+                .set_time_actual_from_string(value, work_day)
+                .map(|_| ())
+                .map_err(|e| error(&e))?,
+            "status" => {
+                if !todo.done() {
+                    todo.set_status_from_string(value).map_err(|e| error(&e))?;
+                }
+            }
+            "color" => todo.set_color_from_string(value),
+            "due" => todo
+                .set_due_from_string(value)
+                .map(|_| ())
+                .map_err(|e| error(&e))?,
+            "t" => todo
+                .set_scheduled_from_string(value)
+                .map(|_| ())
+                .map_err(|e| error(&e))?,
+            "rec" => todo.set_recurrence(todotxt_to_recurrence(value).map_err(|e| error(&e))?),
+            "parent" => todo.set_parent(Some(parse_usize(value).map_err(|e| error(&e))?)),
+            "dep" => todo.set_depends(parse_usize_list(value).map_err(|e| error(&e))?),
+            "note" => todo.set_notes(&unescape_notes(value)),
+            _ => todo.set_sessions(parse_sessions(value).map_err(|e| error(&e))?),
+        }
+    }
+
+    todo.set_description(&description.join(" "));
+    Ok((todo, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORK_DAY: Duration = Duration::from_secs(8 * 3600);
+
+    fn round_trip(todo: &Todo) -> Todo {
+        let line = todo_to_todotxt(todo);
+        let (mut parsed, id) = parse_todotxt_line(&line, WORK_DAY).unwrap();
+        parsed.set_id(id.unwrap());
+        parsed
+    }
+
+    #[test]
+    fn notes_survive_a_round_trip() {
+        let mut todo = Todo::new_with_id(4);
+        todo.set_description("Fix login");
+        todo.set_notes("See https://example.com/issue/12\n\tindented  twice\nC:\\path\\s");
+
+        let line = todo_to_todotxt(&todo);
+        assert_eq!(line.split_whitespace().count(), 4);
+        assert_eq!(round_trip(&todo).to_file(), todo.to_file());
+    }
+
+    #[test]
+    fn description_survives_a_round_trip() {
+        for description in [
+            "vote +1 on C++ see due:later",
+            "@home  t:9 id:x  \\ and \\+ stay",
+            " x leading space, trailing  ",
+            "x (A) 2024-01-01 first words",
+            "links like https://example.com stay",
+        ] {
+            let mut todo = Todo::new_with_id(7);
+            todo.set_description(description);
+            todo.set_projects("web");
+            todo.set_categories("bug");
+            let parsed = round_trip(&todo);
+            assert_eq!(parsed.get_description(), description);
+            assert_eq!(parsed.to_file(), todo.to_file());
+        }
+    }
+
+    #[test]
+    fn escapes_only_words_import_would_misread() {
+        let mut todo = Todo::new_with_id(7);
+        todo.set_description("vote +1 on C++ see due:later at 10:30");
+        assert_eq!(
+            todo_to_todotxt(&todo),
+            "vote \\+1 on C++ see \\due:later at 10:30 id:7"
+        );
+    }
+}
@@ -0,0 +1,562 @@
+use ansi_term::Color;
+use chrono::Local;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{
+    Attribute, Color as TermColor, Print, ResetColor, SetAttribute, SetForegroundColor,
+};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+
+use super::config::*;
+use super::query::*;
+use super::structs::deps::*;
+use super::structs::recur::*;
+use super::structs::table::*;
+use super::structs::tree::*;
+use super::structs::*;
+use super::todoio::*;
+
+// Order of the status when cycling with s and S. Done is left out, d
+// finishes a todo like t do.
+const STATUS_CYCLE: &[TodoStatus] = &[
+    TodoStatus::New,
+    TodoStatus::Backlog,
+    TodoStatus::Next,
+    TodoStatus::Planned,
+    TodoStatus::Doing,
+    TodoStatus::Review,
+];
+
+const KEYS_HELP: &str =
+    "j/k move  s/S status  d do  +/- prio  e desc  p proj  c cat  / filter  a done  r reload  q quit";
+
+// What the bottom line edits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Field {
+    Description,
+    Projects,
+    Categories,
+    Filter,
+}
+
+impl Field {
+    fn label(&self) -> &'static str {
+        match self {
+            Field::Description => "Description",
+            Field::Projects => "Projects",
+            Field::Categories => "Categories",
+            Field::Filter => "Filter",
+        }
+    }
+
+    // The attribute of t set, for the journal.
+    fn attribute(&self) -> &'static str {
+        match self {
+            Field::Description => "desc",
+            Field::Projects => "proj",
+            Field::Categories => "cat",
+            Field::Filter => "",
+        }
+    }
+}
+
+struct Prompt {
+    field: Field,
+    text: String,
+}
+
+struct Tui<'a> {
+    store: &'a mut dyn TodoStore,
+    journal: &'a Journal,
+    config: &'a Config,
+    lock_file_path: &'a Path,
+    style: TableStyle,
+    todos: Vec<Todo>,
+    // The shown todos in tree order, descriptions indented.
+    rows: Vec<Todo>,
+    selected: usize,
+    offset: usize,
+    show_done: bool,
+    filter: String,
+    query: Query,
+    prompt: Option<Prompt>,
+    message: String,
+}
+
+// Switches the terminal back when the TUI ends, also after a panic.
+struct Screen;
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(
+            std::io::stdout(),
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn term_color(color: Color) -> TermColor {
+    match color {
+        Color::Black => TermColor::Black,
+        Color::Red => TermColor::DarkRed,
+        Color::Green => TermColor::DarkGreen,
+        Color::Yellow => TermColor::DarkYellow,
+        Color::Blue => TermColor::DarkBlue,
+        Color::Purple => TermColor::DarkMagenta,
+        Color::Cyan => TermColor::DarkCyan,
+        Color::White => TermColor::Grey,
+        Color::Fixed(v) => TermColor::AnsiValue(v),
+        Color::RGB(r, g, b) => TermColor::Rgb { r, g, b },
+    }
+}
+
+// Cuts or pads a line to the width of the terminal.
+fn fit(s: &str, width: usize) -> String {
+    let mut line: String = s.chars().take(width).collect();
+    let len = line.chars().count();
+    line.extend(std::iter::repeat_n(' ', width - len));
+    line
+}
+
+fn join_cells(cells: &[String], widths: &[usize]) -> String {
+    cells
+        .iter()
+        .zip(widths.iter())
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect::<Vec<String>>()
+        .join("  ")
+}
+
+fn terminal_error(err: std::io::Error) -> String {
+    format!("Terminal error: {}", err)
+}
+
+impl<'a> Tui<'a> {
+    fn selected_id(&self) -> Option<usize> {
+        self.rows.get(self.selected).map(|todo| todo.get_id())
+    }
+
+    fn selected_todo(&self) -> Option<&Todo> {
+        let id = self.selected_id()?;
+        self.todos.iter().find(|todo| todo.get_id() == id)
+    }
+
+    // Reads the todos again, the selection stays on the same todo.
+    fn reload(&mut self) -> Result<(), String> {
+        let selected_id = self.selected_id();
+        self.todos = self
+            .store
+            .load()
+            .map_err(|err| format!("Reading Todos failed: {}", err))?;
+        refresh_blocked(&mut self.todos);
+        self.config.sort.sort(&mut self.todos);
+        self.update_rows(selected_id);
+        Ok(())
+    }
+
+    fn update_rows(&mut self, selected_id: Option<usize>) {
+        let visible: Vec<&Todo> = self
+            .todos
+            .iter()
+            .filter(|todo| (self.show_done || !todo.done()) && self.query.matches(todo))
+            .collect();
+        self.rows = tree_order(&visible)
+            .into_iter()
+            .map(|(todo, depth)| tree_display_todo(todo, depth, &self.todos))
+            .collect();
+        self.selected = selected_id
+            .and_then(|id| self.rows.iter().position(|todo| todo.get_id() == id))
+            .unwrap_or_else(|| self.selected.min(self.rows.len().saturating_sub(1)));
+    }
+
+    // Changes the selected todo like `t set <command>` would, with the
+    // lock held and the todo read fresh, so other t commands in between
+    // are not overwritten.
+    fn change<F>(&mut self, attribute: &str, value: &str, apply: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Todo) -> Result<(), String>,
+    {
+        let id = match self.selected_id() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        {
+            let _lock = lock_todo_files(self.lock_file_path).map_err(|err| err.to_string())?;
+            let id_before = self
+                .store
+                .peek_id()
+                .map_err(|err| format!("Error reading ID: {}", err))?;
+            let mut todo = match self.store.get(id) {
+                Ok(Some(v)) => v,
+                Ok(None) => return Err(format!("No Todo with ID {}", id)),
+                Err(err) => return Err(format!("Reading Todos failed: {}", err)),
+            };
+            let before = todo.clone();
+            apply(&mut todo)?;
+            self.store
+                .update(&[todo.clone()])
+                .map_err(|err| format!("Writing Todo file failed: {}", err))?;
+
+            let entry = JournalEntry::new(
+                &format!("set {} {} {}", attribute, id, value),
+                id_before,
+                id_before,
+                vec![before],
+                vec![todo],
+            );
+            if !entry.changes_nothing() {
+                if let Err(err) = self.journal.record(&entry) {
+                    self.message = format!("Error writing journal: {}", err);
+                }
+            }
+        }
+        self.reload()
+    }
+
+    fn cycle_status(&mut self, step: isize) -> Result<(), String> {
+        let status = match self.selected_todo() {
+            Some(v) => v.get_status(),
+            None => return Ok(()),
+        };
+        let len = STATUS_CYCLE.len() as isize;
+        let index = match STATUS_CYCLE.iter().position(|v| *v == status) {
+            Some(v) => (v as isize + step).rem_euclid(len),
+            // A done todo opens again at either end of the cycle.
+            None if step > 0 => 0,
+            None => len - 1,
+        };
+        let status = STATUS_CYCLE[index as usize];
+        self.change("stat", &status.to_string(), |todo| {
+            todo.set_status(status).map_err(|err| err.to_string())
+        })
+    }
+
+    // Marks the selected todo and its open subtasks as done like `t do`, so
+    // the running session stops and a recurring todo comes back.
+    fn finish(&mut self) -> Result<(), String> {
+        let id = match self.selected_id() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        {
+            let _lock = lock_todo_files(self.lock_file_path).map_err(|err| err.to_string())?;
+            let id_before = self
+                .store
+                .peek_id()
+                .map_err(|err| format!("Error reading ID: {}", err))?;
+            let todos = self
+                .store
+                .load()
+                .map_err(|err| format!("Reading Todos failed: {}", err))?;
+            let ids = with_descendants(&todos, &[id]);
+            let before: Vec<Todo> = todos
+                .iter()
+                .filter(|todo| ids.contains(&todo.get_id()) && !todo.done())
+                .cloned()
+                .collect();
+            if before.is_empty() {
+                return Err(format!("Todo {} is already done", id));
+            }
+
+            let (mut after, next_instances) =
+                finish_todos(&before, super::now_for_timer(), id_before);
+            let id_after = id_before + next_instances.len();
+            after.extend(next_instances);
+            self.store
+                .store_changes(&after, &[])
+                .map_err(|err| format!("Writing Todo file failed: {}", err))?;
+            if id_after != id_before {
+                self.store
+                    .set_next_id(id_after)
+                    .map_err(|err| format!("Error setting ID: {}", err))?;
+            }
+
+            let entry =
+                JournalEntry::new(&format!("do {}", id), id_before, id_after, before, after);
+            if let Err(err) = self.journal.record(&entry) {
+                self.message = format!("Error writing journal: {}", err);
+            }
+        }
+        self.reload()
+    }
+
+    fn change_priority(&mut self, step: isize) -> Result<(), String> {
+        let priority = match self.selected_todo() {
+            Some(v) => v.get_priority() + step,
+            None => return Ok(()),
+        };
+        self.change("prio", &priority.to_string(), |todo| {
+            todo.set_priority(priority);
+            Ok(())
+        })
+    }
+
+    fn open_prompt(&mut self, field: Field) {
+        let text = match (field, self.selected_todo()) {
+            (Field::Filter, _) => self.filter.clone(),
+            (Field::Description, Some(todo)) => todo.get_description(),
+            (Field::Projects, Some(todo)) => todo.get_projects(),
+            (Field::Categories, Some(todo)) => todo.get_categories(),
+            (_, None) => return,
+        };
+        self.prompt = Some(Prompt { field, text });
+    }
+
+    // The filter is applied while typing, as long as it parses.
+    fn set_filter(&mut self, filter: &str) {
+        self.filter = filter.to_string();
+        match Query::parse(filter, self.config.work_day) {
+            Ok(v) => {
+                self.query = v;
+                self.message.clear();
+                let selected_id = self.selected_id();
+                self.update_rows(selected_id);
+            }
+            Err(err) => self.message = err.to_string(),
+        }
+    }
+
+    fn submit_prompt(&mut self, prompt: Prompt) -> Result<(), String> {
+        let text = prompt.text;
+        match prompt.field {
+            Field::Filter => Ok(()),
+            Field::Description => self.change(prompt.field.attribute(), &text, |todo| {
+                todo.set_description(&text);
+                Ok(())
+            }),
+            Field::Projects => self.change(prompt.field.attribute(), &text, |todo| {
+                todo.set_projects(&text);
+                Ok(())
+            }),
+            Field::Categories => self.change(prompt.field.attribute(), &text, |todo| {
+                todo.set_categories(&text);
+                Ok(())
+            }),
+        }
+    }
+
+    fn handle_prompt_key(&mut self, key: KeyEvent) -> Result<(), String> {
+        let mut prompt = match self.prompt.take() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        match key.code {
+            KeyCode::Enter => return self.submit_prompt(prompt),
+            KeyCode::Esc => {
+                if prompt.field == Field::Filter {
+                    self.set_filter("");
+                }
+                return Ok(());
+            }
+            KeyCode::Backspace => {
+                prompt.text.pop();
+            }
+            KeyCode::Char(c) => prompt.text.push(c),
+            _ => {}
+        }
+        if prompt.field == Field::Filter {
+            self.set_filter(&prompt.text);
+        }
+        self.prompt = Some(prompt);
+        Ok(())
+    }
+
+    // Returns true when the TUI should end.
+    fn handle_key(&mut self, key: KeyEvent, page: usize) -> Result<bool, String> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Ok(true);
+        }
+        if self.prompt.is_some() {
+            self.handle_prompt_key(key)?;
+            return Ok(false);
+        }
+
+        let last = self.rows.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('q') => return Ok(true),
+            KeyCode::Esc if self.filter.is_empty() => return Ok(true),
+            KeyCode::Esc => self.set_filter(""),
+            KeyCode::Char('j') | KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::PageDown => self.selected = (self.selected + page).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(page),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.selected = last,
+            KeyCode::Char('s') => self.cycle_status(1)?,
+            KeyCode::Char('S') => self.cycle_status(-1)?,
+            KeyCode::Char('d') => self.finish()?,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.change_priority(1)?,
+            KeyCode::Char('-') => self.change_priority(-1)?,
+            KeyCode::Char('e') => self.open_prompt(Field::Description),
+            KeyCode::Char('p') => self.open_prompt(Field::Projects),
+            KeyCode::Char('c') => self.open_prompt(Field::Categories),
+            KeyCode::Char('/') => self.open_prompt(Field::Filter),
+            KeyCode::Char('a') => {
+                self.show_done = !self.show_done;
+                let selected_id = self.selected_id();
+                self.update_rows(selected_id);
+            }
+            KeyCode::Char('r') => self.reload()?,
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height.max(3) as usize);
+        let list_height = height - 2;
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + list_height {
+            self.offset = self.selected + 1 - list_height;
+        }
+
+        let header: Vec<String> = self
+            .style
+            .header()
+            .trim_end()
+            .split('\t')
+            .map(String::from)
+            .collect();
+        let now = Local::now().naive_local();
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|todo| {
+                self.style
+                    .row(todo, now)
+                    .split('\t')
+                    .map(String::from)
+                    .collect()
+            })
+            .collect();
+        let mut widths: Vec<usize> = header.iter().map(|v| v.chars().count()).collect();
+        for row in cells.iter() {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        queue!(
+            out,
+            terminal::Clear(ClearType::All),
+            cursor::MoveTo(0, 0),
+            SetAttribute(Attribute::Bold),
+            Print(fit(&join_cells(&header, &widths), width)),
+            SetAttribute(Attribute::Reset)
+        )?;
+        if self.rows.is_empty() {
+            queue!(out, cursor::MoveTo(0, 1), Print("No Todos"))?;
+        }
+        for (i, row) in cells.iter().enumerate().skip(self.offset).take(list_height) {
+            queue!(out, cursor::MoveTo(0, (i - self.offset + 1) as u16))?;
+            if self.config.table.color {
+                let todo = &self.rows[i];
+                let color = if todo.done() {
+                    Color::Fixed(8)
+                } else {
+                    todo.get_color()
+                };
+                queue!(out, SetForegroundColor(term_color(color)))?;
+            }
+            if i == self.selected {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                out,
+                Print(fit(&join_cells(row, &widths), width)),
+                SetAttribute(Attribute::Reset),
+                ResetColor
+            )?;
+        }
+
+        let bottom = match &self.prompt {
+            Some(prompt) => format!("{}: {}", prompt.field.label(), prompt.text),
+            None if !self.message.is_empty() => self.message.clone(),
+            None if !self.filter.is_empty() => format!("[{}]  {}", self.filter, KEYS_HELP),
+            None => KEYS_HELP.to_string(),
+        };
+        queue!(
+            out,
+            cursor::MoveTo(0, (height - 1) as u16),
+            Print(fit(&bottom, width))
+        )?;
+        match &self.prompt {
+            Some(_) => queue!(
+                out,
+                cursor::MoveTo(
+                    bottom.chars().count().min(width) as u16,
+                    (height - 1) as u16
+                ),
+                cursor::Show
+            )?,
+            None => queue!(out, cursor::Hide)?,
+        }
+        out.flush()
+    }
+}
+
+// t tui: a full screen list of the todos of the current list. Every change
+// is written right away and journaled like the matching t set or t do, so
+// t undo takes it back.
+pub fn run_tui(
+    store: &mut dyn TodoStore,
+    journal: &Journal,
+    config: &Config,
+    lock_file_path: &Path,
+) -> Result<(), String> {
+    if !std::io::stdout().is_terminal() {
+        return Err(String::from("t tui needs a terminal"));
+    }
+
+    let mut tui = Tui {
+        store,
+        journal,
+        config,
+        lock_file_path,
+        style: TableStyle {
+            color: false,
+            ..config.table.clone()
+        },
+        todos: Vec::new(),
+        rows: Vec::new(),
+        selected: 0,
+        offset: 0,
+        show_done: false,
+        filter: String::new(),
+        query: Query::parse("", config.work_day).map_err(|err| err.to_string())?,
+        prompt: None,
+        message: String::new(),
+    };
+    tui.reload()?;
+
+    let mut stdout = std::io::stdout();
+    terminal::enable_raw_mode().map_err(terminal_error)?;
+    let _screen = Screen;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide).map_err(terminal_error)?;
+
+    loop {
+        tui.draw(&mut stdout).map_err(terminal_error)?;
+        // A resize only needs the redraw above.
+        let key = match event::read().map_err(terminal_error)? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        let page = terminal::size()
+            .map(|(_, h)| h as usize)
+            .unwrap_or(3)
+            .max(3)
+            - 2;
+        tui.message.clear();
+        match tui.handle_key(key, page) {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(err) => tui.message = err,
+        }
+    }
+}